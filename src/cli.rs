@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 
 use anyhow::Context;
-use clap::{Parser, ValueEnum};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand, ValueEnum};
 use log::LevelFilter;
 
 #[derive(Parser, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Default)]
@@ -21,6 +22,45 @@ pub struct Cli {
     /// Set logging level to use
     #[arg(long, short, value_enum, default_value_t = LogLevel::Warn)]
     pub log_level: LogLevel,
+
+    /// Monitor as usual but write notifications to PATH (or stdout if no PATH given) instead of sending them
+    #[arg(long, value_name = "PATH")]
+    pub dry_run: Option<Option<PathBuf>>,
+
+    /// If not specified monitoring is started
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum Command {
+    /// Sends a test message through each configured notification channel without starting monitoring
+    ///
+    /// Exits with a non-zero status if any channel fails
+    NotifyTest,
+
+    /// Replays recorded responses through the monitoring logic using the current config and prints
+    /// the events that would have been produced, nothing is sent
+    Simulate {
+        /// Event log files to replay. If not specified all files in the events folder are used
+        #[arg(value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Lists the recorded outages of the targets
+    Outages {
+        /// Only list outages of the target with this display name
+        #[arg(long)]
+        target: Option<String>,
+
+        /// Only list outages that ended on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<NaiveDate>,
+
+        /// Output one JSON object per line
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 impl Cli {
@@ -1,10 +1,18 @@
 use std::{fs, path::Path};
 
-use anyhow::Context;
-use log::debug;
+use anyhow::{bail, Context};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::{Seconds, Target};
+use crate::{
+    mqtt::MqttConfig,
+    notification::NotificationConfig,
+    report::ReportConfig,
+    schedule::{Schedule, ScheduleConfig},
+    Seconds, Target,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -32,8 +40,24 @@ pub struct Config {
     #[serde(default = "Config::default_min_time_before_first_down_notification")]
     pub min_time_before_first_down_notification: Seconds,
 
-    /// If set the time of day I'm still alive messages should be sent otherwise no messages sent
-    pub keep_alive_time_of_day: Option<chrono::NaiveTime>,
+    /// If set when I'm still alive messages should be sent otherwise no messages sent. Not used
+    /// if `report` is set
+    pub keep_alive: Option<ScheduleConfig>,
+
+    /// Replaced by `keep_alive`, still accepted and converted to a daily schedule in the local
+    /// timezone when the config is loaded
+    #[serde(default, skip_serializing)]
+    pub keep_alive_time_of_day: Option<NaiveTime>,
+
+    /// If set summary reports are sent instead of keep alive messages (they include the uptime)
+    pub report: Option<ReportConfig>,
+
+    /// Settings for notification channels
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+
+    /// If set the status of each target is published to this MQTT broker
+    pub mqtt: Option<MqttConfig>,
 }
 
 impl Config {
@@ -41,11 +65,48 @@ impl Config {
         debug!("Loading Config from: {config_path:?}");
         let file_contents = fs::read_to_string(config_path)
             .with_context(|| format!("failed to read contents of {config_path:?}"))?;
-        let result = serde_json::from_str(&file_contents)
+        let mut result: Config = serde_json::from_str(&file_contents)
             .with_context(|| format!("failed to parse contents of {config_path:?}"))?;
+        result
+            .notifications
+            .check_legacy_files(Path::new("."))
+            .context("legacy notification settings need to be moved into the config")?;
+        result
+            .migrate_keep_alive_time_of_day()
+            .with_context(|| format!("invalid keep alive settings in {config_path:?}"))?;
         Ok(result)
     }
 
+    /// Moves the deprecated `keep_alive_time_of_day` into `keep_alive`
+    fn migrate_keep_alive_time_of_day(&mut self) -> anyhow::Result<()> {
+        let Some(time) = self.keep_alive_time_of_day.take() else {
+            return Ok(());
+        };
+        let replacement = |timezone: &str| {
+            format!(
+                r#""keep_alive": {{"timezone": "{timezone}", "at": ["{}"]}}"#,
+                time.format("%H:%M")
+            )
+        };
+        if self.keep_alive.is_some() {
+            bail!(
+                "both keep_alive_time_of_day and keep_alive are set. keep_alive_time_of_day is \
+                replaced by keep_alive so remove it"
+            );
+        }
+        let timezone = or_utc(local_timezone());
+        warn!(
+            "keep_alive_time_of_day is deprecated. Replace it with {}",
+            replacement(timezone.name())
+        );
+        self.keep_alive = Some(ScheduleConfig {
+            timezone,
+            at: vec![Schedule::daily(time)],
+            missed: Default::default(),
+        });
+        Ok(())
+    }
+
     fn default_timeout() -> Seconds {
         3.into()
     }
@@ -67,6 +128,20 @@ impl Config {
     }
 }
 
+fn local_timezone() -> anyhow::Result<Tz> {
+    let name = iana_time_zone::get_timezone().context("failed to get the local timezone")?;
+    name.parse()
+        .map_err(|e| anyhow::anyhow!("unknown local timezone {name:?}: {e}"))
+}
+
+/// Falls back to UTC if the timezone could not be determined
+fn or_utc(timezone: anyhow::Result<Tz>) -> Tz {
+    timezone.unwrap_or_else(|e| {
+        warn!("using UTC as the local timezone could not be detected: {e:?}");
+        Tz::UTC
+    })
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -83,13 +158,22 @@ mod tests {
                 display_name: None,
                 timeout: None,
                 disabled: false,
+                tags: vec![],
             }],
             default_timeout: 5.into(),
             ping_repeat_freq: 1.into(),
             min_time_between_write: 1.into(),
             notify_remind_interval: 1.into(),
             min_time_before_first_down_notification: 1.into(),
-            keep_alive_time_of_day: chrono::NaiveTime::from_hms_opt(18, 2, 3),
+            keep_alive: Some(ScheduleConfig {
+                timezone: chrono_tz::Europe::London,
+                at: vec![Schedule::daily(NaiveTime::from_hms_opt(18, 2, 3).unwrap())],
+                missed: Default::default(),
+            }),
+            keep_alive_time_of_day: None,
+            report: None,
+            notifications: Default::default(),
+            mqtt: None,
         };
 
         println!("{}", serde_json::to_string(&conf).unwrap());
@@ -97,6 +181,51 @@ mod tests {
         panic!("this test is expected to fail to show the serialized Config");
     }
 
+    #[test]
+    fn keep_alive_time_of_day_is_converted_to_daily_schedule() {
+        // Arrange
+        let mut config: Config =
+            serde_json::from_str(r#"{"targets": [], "keep_alive_time_of_day": "07:30:00"}"#)
+                .unwrap();
+
+        // Act
+        let actual = config.migrate_keep_alive_time_of_day();
+
+        // Assert
+        assert!(actual.is_ok(), "{actual:?}");
+        let keep_alive = config.keep_alive.unwrap();
+        assert_eq!(keep_alive.timezone, or_utc(local_timezone()));
+        assert_eq!(
+            keep_alive.at,
+            [Schedule::daily(NaiveTime::from_hms_opt(7, 30, 0).unwrap())]
+        );
+        assert!(config.keep_alive_time_of_day.is_none());
+    }
+
+    #[test]
+    fn undetected_timezone_falls_back_to_utc() {
+        let actual = or_utc(Err(anyhow::anyhow!("failed to get the local timezone")));
+
+        assert_eq!(actual, Tz::UTC);
+    }
+
+    #[test]
+    fn keep_alive_time_of_day_and_keep_alive_both_set_is_error() {
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "targets": [],
+                "keep_alive_time_of_day": "07:30:00",
+                "keep_alive": {"timezone": "UTC", "at": ["07:30"]}
+            }"#,
+        )
+        .unwrap();
+
+        let actual = config.migrate_keep_alive_time_of_day();
+
+        let err = actual.unwrap_err().to_string();
+        assert!(err.contains("replaced by keep_alive"), "{err}");
+    }
+
     /// Ensure sample files are valid json
     #[rstest]
     #[case("sample_config_full/config.json")]
@@ -104,6 +233,12 @@ mod tests {
     fn load_sample_config(#[case] filename: &str) {
         // Arrange
         let path = Path::new(filename);
+        let _env = crate::secret::ENV_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // Secrets referenced by the full sample
+        std::env::set_var("CONN_MON_DISCORD_WEBHOOK", "0000/change-me");
+        std::env::set_var("CONN_MON_SMTP_PASSWORD", "change-me");
 
         // Act
         let actual = Config::load_from(path);
@@ -1,25 +1,33 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     fs::{canonicalize, create_dir_all, File},
-    io::Write,
+    io::{BufRead, Write},
     path::{Path, PathBuf},
-    sync::mpsc::{self, Receiver, Sender},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
     thread,
     time::Instant,
 };
 
 use anyhow::{bail, Context};
-use chrono::{Local, NaiveTime, Timelike};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
     config::Config,
+    mqtt::MqttPublisher,
+    notification::dispatcher::{Dispatcher, DryRun},
+    outage,
     ping::{PingResponse, Target},
-    state_management::{Event, MonitorState},
-    Discord, Email,
+    report::{Report, ReportPeriod},
+    secret,
+    state_management::{Event, MonitorState, Status},
+    Milliseconds, Seconds,
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,6 +40,7 @@ pub struct TimestampedResponse {
 /// Manages a target, tracking things like where to write the info to disk and what is pending being written
 pub struct TargetHandler<'a> {
     host_disp_name: String,
+    host: String,
     pending_for_file: Vec<TimestampedResponse>,
     file_handle: File,
     file_path: PathBuf,
@@ -42,7 +51,7 @@ pub struct TargetHandler<'a> {
 }
 
 impl<'a> TargetHandler<'a> {
-    const BASE_FOLDER: &'static str = "events";
+    pub(crate) const BASE_FOLDER: &'static str = "events";
     fn new(target: &Target, config: &'a Config) -> anyhow::Result<Self> {
         debug!("Creating new TargetHandler for: {target}");
         let host_disp_name = format!("{target}");
@@ -52,6 +61,7 @@ impl<'a> TargetHandler<'a> {
                 .context("failed creating file handle during TargetInfo initialization")?;
         let result = Self {
             host_disp_name,
+            host: target.host.clone(),
             pending_for_file: Default::default(),
             file_handle,
             file_path,
@@ -100,6 +110,14 @@ impl<'a> TargetHandler<'a> {
         Ok((path, result))
     }
 
+    /// Date and target of an event log file based on the names given by [`Self::create_file_handle`]
+    pub(crate) fn parse_file_name(path: &Path) -> Option<(NaiveDate, &str)> {
+        let file_name = path.file_name()?.to_str()?;
+        let (date, target) = file_name.strip_suffix(" events.log")?.split_once(' ')?;
+        let date = NaiveDate::parse_from_str(date, "%F").ok()?;
+        Some((date, target))
+    }
+
     fn create_time_part_for_filename() -> String {
         format!("{}", Local::now().format("%F"))
     }
@@ -122,10 +140,27 @@ impl<'a> TargetHandler<'a> {
     fn receive_response(
         &mut self,
         response: TimestampedResponse,
+        status_board: &StatusBoard,
+        mqtt: Option<&MqttPublisher>,
     ) -> anyhow::Result<Option<EventMessage>> {
         let event = self.state.process_response(&response);
+        if let Some(outage) = self.state.take_finished_outage() {
+            // Not returned as an error so the event is still sent
+            if let Err(e) =
+                outage::record(Path::new(Self::BASE_FOLDER), &self.host_disp_name, &outage)
+            {
+                error!("failed to record outage of {}: {e:?}", self.host_disp_name);
+            }
+        }
+        let status = status_board.update(&self.host_disp_name, self.state.status(), &response);
+        if let (Some(mqtt), Some(status)) = (mqtt, status) {
+            mqtt.publish(&self.host_disp_name, &status, &response);
+        }
         let result = if let Some(event) = event {
-            Some(EventMessage::new(self.host_disp_name.to_string(), event))
+            Some(
+                EventMessage::new(self.host_disp_name.to_string(), event)
+                    .with_host(self.host.clone()),
+            )
         } else {
             None
         };
@@ -145,6 +180,11 @@ impl<'a> TargetHandler<'a> {
                 return Ok(()); // Do nothing enough time has not passed yet or nothing to write
             }
         }
+        self.flush()
+    }
+
+    /// Writes all pending responses to disk without waiting for `min_time_between_write`
+    fn flush(&mut self) -> anyhow::Result<()> {
         debug!(
             "{} has {} pending messages being written to disk at {:?}",
             self.host_disp_name,
@@ -173,12 +213,22 @@ impl TargetID {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub struct Timestamp(String);
 
 impl Timestamp {
+    const FORMAT: &'static str = "%F %T";
+
     pub fn new() -> Self {
-        Self(format!("{}", Local::now().format("%F %T")))
+        Self(format!("{}", Local::now().format(Self::FORMAT)))
+    }
+
+    /// The point in time represented, `None` if not in the expected format or invalid in the local timezone
+    pub fn as_date_time(&self) -> Option<DateTime<Local>> {
+        NaiveDateTime::parse_from_str(&self.0, Self::FORMAT)
+            .ok()?
+            .and_local_timezone(Local)
+            .earliest()
     }
 }
 
@@ -218,27 +268,120 @@ impl ResponseMessage {
     }
 }
 
-#[derive(Debug)]
-struct EventMessage {
-    host_disp_name: String,
-    timestamp: Timestamp,
-    event: Event,
+#[derive(Debug, Clone)]
+pub(crate) struct EventMessage {
+    pub(crate) host_disp_name: String,
+    /// Address of the target, `None` for system messages
+    pub(crate) host: Option<String>,
+    pub(crate) timestamp: Timestamp,
+    pub(crate) event: Event,
+    /// Wording of the event
+    pub(crate) text: String,
+    /// Full message including the timestamp and target
+    pub(crate) message: String,
 }
 
 impl EventMessage {
     pub fn new(host_disp_name: String, event: Event) -> Self {
-        Self {
+        let mut result = Self {
             host_disp_name,
+            host: None,
             timestamp: Timestamp::new(),
+            text: event.to_string(),
             event,
-        }
+            message: String::new(),
+        };
+        result.message = Self::default_message(&result);
+        result
+    }
+
+    pub(crate) fn with_host(mut self, host: String) -> Self {
+        self.host = Some(host);
+        self
     }
 
-    fn system_message(event: Event) -> Self {
+    pub(crate) fn default_message(event_msg: &EventMessage) -> String {
+        let EventMessage {
+            host_disp_name: name,
+            timestamp,
+            text,
+            ..
+        } = event_msg;
+        format!("{timestamp} - {name} - {text}")
+    }
+
+    pub(crate) fn system_message(event: Event) -> Self {
         Self::new("SYSTEM_MSG".to_string(), event)
     }
 }
 
+/// Latest known status of a target
+#[derive(Debug, Clone)]
+pub(crate) struct TargetStatus {
+    pub(crate) status: Status,
+    /// When the target changed into the current status
+    pub(crate) since: Timestamp,
+    pub(crate) last_rtt: Option<Milliseconds>,
+}
+
+/// Status of all targets, shared with the event dispatch thread for use in notifications
+#[derive(Debug, Default, Clone)]
+pub(crate) struct StatusBoard(Arc<Mutex<BTreeMap<String, TargetStatus>>>);
+
+impl StatusBoard {
+    /// Returns the updated status of the target
+    fn update(
+        &self,
+        host_disp_name: &str,
+        status: Status,
+        response: &TimestampedResponse,
+    ) -> Option<TargetStatus> {
+        let Ok(mut board) = self.0.lock() else {
+            error!("status board lock poisoned. Unable to update status of {host_disp_name}");
+            return None;
+        };
+        let last_rtt = match response.response {
+            PingResponse::Time(ms) => Some(ms),
+            _ => None,
+        };
+        match board.get_mut(host_disp_name) {
+            Some(existing) => {
+                if existing.status != status {
+                    existing.status = status;
+                    existing.since = response.timestamp.clone();
+                }
+                if last_rtt.is_some() {
+                    existing.last_rtt = last_rtt;
+                }
+                Some(existing.clone())
+            }
+            None => {
+                let result = TargetStatus {
+                    status,
+                    since: response.timestamp.clone(),
+                    last_rtt,
+                };
+                board.insert(host_disp_name.to_string(), result.clone());
+                Some(result)
+            }
+        }
+    }
+
+    /// Copy of the current status of all targets ordered by name
+    pub(crate) fn snapshot(&self) -> Vec<(String, TargetStatus)> {
+        match self.0.lock() {
+            Ok(board) => board
+                .iter()
+                .map(|(name, status)| (name.clone(), status.clone()))
+                .collect(),
+            Err(e) => {
+                error!("status board lock poisoned: {e}");
+                vec![]
+            }
+        }
+    }
+}
+
 /// Handles all incoming events and sends them to the right handler based on the ID in the message
 pub struct ResponseManager<'a> {
     rx_ping_response: Receiver<ResponseMessage>,
@@ -246,22 +389,61 @@ pub struct ResponseManager<'a> {
     target_map: HashMap<TargetID, TargetHandler<'a>>,
     next_id: TargetID,
     config: &'a Config,
+    status_board: StatusBoard,
+    mqtt: Option<MqttPublisher>,
+    /// Reports are requested by the report schedule and built in the receive loop so that
+    /// responses not yet written to disk can be flushed first
+    tx_report_requests: Sender<ReportRequest>,
+    rx_report_requests: Receiver<ReportRequest>,
+}
+
+#[derive(Debug)]
+struct ReportRequest {
+    period: ReportPeriod,
+    uptime: Seconds,
 }
 
 impl<'a> ResponseManager<'a> {
     pub fn new(
         rx_ping_response: Receiver<ResponseMessage>,
         config: &'a Config,
+        dry_run: Option<DryRun>,
     ) -> anyhow::Result<Self> {
         debug!("New event manager being created");
         let (tx_events, rx) = mpsc::channel();
-        Self::start_event_thread(rx)?;
+        let status_board = StatusBoard::default();
+        let is_dry_run = dry_run.is_some();
+        Self::start_event_thread(rx, config, status_board.clone(), dry_run)?;
+        let mqtt = match &config.mqtt {
+            Some(_) if is_dry_run => {
+                info!("MQTT publishing disabled for dry run");
+                None
+            }
+            Some(mqtt_config) => {
+                let targets = config
+                    .targets
+                    .iter()
+                    .filter(|t| !t.disabled)
+                    .map(|t| t.to_string())
+                    .collect();
+                Some(
+                    MqttPublisher::start(mqtt_config, targets)
+                        .context("failed to start mqtt publisher")?,
+                )
+            }
+            None => None,
+        };
+        let (tx_report_requests, rx_report_requests) = mpsc::channel();
         Ok(Self {
             rx_ping_response,
             tx_events,
             target_map: Default::default(),
             next_id: Default::default(),
             config,
+            status_board,
+            mqtt,
+            tx_report_requests,
+            rx_report_requests,
         })
     }
 
@@ -286,7 +468,7 @@ impl<'a> ResponseManager<'a> {
                 .expect("failed to get handler for ID");
 
             match handler
-                .receive_response(msg.into_response())
+                .receive_response(msg.into_response(), &self.status_board, self.mqtt.as_ref())
                 .context("failed to handle response")
             {
                 Ok(Some(event_msg)) => {
@@ -303,130 +485,110 @@ impl<'a> ResponseManager<'a> {
                     error!("{e:?}");
                     if let Err(err) =
                         self.tx_events
-                            .send(EventMessage::system_message(Event::SystemError(format!(
-                                "{e:?}"
-                            ))))
+                            .send(EventMessage::system_message(Event::SystemError(
+                                secret::redact(&format!("{e:?}")),
+                            )))
                     {
                         error!("{err:?}");
                     }
                 }
             }
+
+            while let Ok(request) = self.rx_report_requests.try_recv() {
+                self.send_report(request);
+            }
         }
     }
 
-    fn start_event_thread(rx: Receiver<EventMessage>) -> anyhow::Result<()> {
-        let discord: Option<Discord> = match Discord::new() {
-            Ok(d) => Some(d),
-            Err(e) => {
+    /// Writes the pending responses of every target to disk and sends the report built from them
+    fn send_report(&mut self, request: ReportRequest) {
+        for handler in self.target_map.values_mut() {
+            // The report is still sent but will be missing the most recent responses
+            if let Err(e) = handler.flush() {
                 error!(
-                    "Unable to setup discord. Discord notifications will be disabled.Error:\n{e:?}"
+                    "failed to write responses of {} before report: {e:?}",
+                    handler.host_disp_name
                 );
-                None
-            }
-        };
-        let email: Option<Email> = match Email::new() {
-            Ok(client) => Some(client),
-            Err(e) => {
-                error!("Unable to setup email. Email notifications will be disabled. {e:?}");
-                None
             }
-        };
+        }
+        let event = Self::create_report(request.period, request.uptime);
+        if let Err(e) = self
+            .tx_events
+            .send(EventMessage::system_message(event))
+            .context("failed to send report event. Event dispatch thread likely panicked")
+        {
+            error!("{e:?}");
+        }
+    }
+
+    fn start_event_thread(
+        rx: Receiver<EventMessage>,
+        config: &Config,
+        status_board: StatusBoard,
+        dry_run: Option<DryRun>,
+    ) -> anyhow::Result<()> {
+        let dispatcher = Dispatcher::new(config, status_board, dry_run);
         thread::Builder::new()
             .name("EventDispatch".to_string())
-            .spawn(move || loop {
-                let event_message = rx.recv().expect("failed to receive event message");
-
-                let EventMessage {
-                    host_disp_name: name,
-                    timestamp,
-                    event,
-                } = event_message;
-                let notification_message = format!("{timestamp} - {name} - {event}",);
-                let msg = &notification_message;
-
-                if Event::Startup == event {
-                    // Test all comms methods
-                    if discord.is_some() && !Self::send_via_discord(discord.as_ref(), msg) {
-                        error!("Test of discord failed");
-                    }
-                    if email.is_some() && !Self::send_via_email(email.as_ref(), msg) {
-                        error!("Test of email failed");
-                    }
-                } else if !Self::send_via_discord(discord.as_ref(), msg)
-                    && !Self::send_via_email(email.as_ref(), msg)
-                {
-                    error!("failed to send notification via all means. Message was: {msg:?}");
-                }
-            })
+            .spawn(move || dispatcher.run(rx))
             .context("failed to start event loop thread")?;
         Ok(())
     }
 
-    /// Attempts to send the message via discord, if there is no discord set or there is an error it returns false
-    /// Not sure if a true is guaranteed message sent but at least we couldn't detect the error
-    fn send_via_discord(discord: Option<&Discord>, msg: &str) -> bool {
-        match discord {
-            Some(discord) => match discord.send(msg) {
-                Ok(()) => true,
-                Err(e) => {
-                    error!("failed to send message via discord: {e:?}");
-                    false
-                }
-            },
-            None => {
-                debug!("Discord not set. Message not sent via discord");
-                false
-            }
-        }
-    }
-
-    /// Attempts to send the message via email, if there is no email set or there is an error it returns false
-    /// Not sure if a true is guaranteed message sent but at least we couldn't detect the error
-    fn send_via_email(email: Option<&Email>, msg: &str) -> bool {
-        match email {
-            Some(email) => match email.send(msg) {
-                Ok(()) => true,
-                Err(e) => {
-                    error!("failed to send message via email: {e:?}");
-                    false
-                }
-            },
-            None => {
-                debug!("Email not set. Message not sent via email");
-                false
-            }
-        }
-    }
-
+    /// Sends the startup event and starts the keep alive and report schedules
     pub(crate) fn start_keep_alive(&self) -> anyhow::Result<()> {
-        let tx = self.tx_events.clone();
         let start = Instant::now();
-        let keep_alive_time_of_day = self.config.keep_alive_time_of_day;
 
-        tx.send(EventMessage::system_message(Event::Startup))
+        self.tx_events
+            .send(EventMessage::system_message(Event::Startup))
             .expect("failed to send startup event");
 
-        if let Some(target_time) = keep_alive_time_of_day {
-            warn!("Keep Alive time set for: {target_time:?}");
-            thread::Builder::new()
-                .name("KeepAlive".to_string())
-                .spawn(move || loop {
-                    let sleep_duration = seconds_to_time(target_time).expect(
-                        "This should always be valid as we are only asking for at most 1 day",
-                    );
-                    thread::sleep(sleep_duration);
-                    tx.send(EventMessage::system_message(Event::IAmAlive(
-                        start.elapsed().as_secs().into(),
-                    )))
-                    .expect("failed to send keep alive event");
-                })
-                .context("failed to start keep alive thread")?;
+        if self.config.report.is_some() {
+            warn!("Keep Alive notifications replaced by reports");
+        } else if let Some(schedule) = &self.config.keep_alive {
+            warn!("Keep Alive scheduled for: {schedule}");
+            let tx = self.tx_events.clone();
+            schedule.clone().spawn("KeepAlive", move || {
+                tx.send(EventMessage::system_message(Event::IAmAlive(
+                    start.elapsed().as_secs().into(),
+                )))
+                .expect("failed to send keep alive event");
+            })?;
         } else {
             warn!("Keep Alive notifications disabled");
         }
+
+        if let Some(report) = &self.config.report {
+            warn!("Reports scheduled for: {}", report.schedule);
+            let tx = self.tx_report_requests.clone();
+            let period = report.period;
+            report.schedule.clone().spawn("Report", move || {
+                tx.send(ReportRequest {
+                    period,
+                    uptime: start.elapsed().as_secs().into(),
+                })
+                .expect("failed to send report request");
+            })?;
+        }
         Ok(())
     }
 
+    /// A report for the period that just ended or an error event if it could not be created
+    fn create_report(period: ReportPeriod, uptime: Seconds) -> Event {
+        match Report::from_event_files(
+            Path::new(TargetHandler::BASE_FOLDER),
+            period,
+            uptime,
+            Local::now(),
+        ) {
+            Ok(report) => Event::Report(Box::new(report)),
+            Err(e) => {
+                error!("failed to create report: {e:?}");
+                Event::SystemError(format!("failed to create report: {e:#}"))
+            }
+        }
+    }
+
     pub(crate) fn log_events_output_folder(&self) -> anyhow::Result<()> {
         let event_output_folder = TargetHandler::BASE_FOLDER;
         let event_output_folder = canonicalize(event_output_folder)
@@ -438,25 +600,77 @@ impl<'a> ResponseManager<'a> {
     }
 }
 
-fn seconds_to_time(target_time: NaiveTime) -> anyhow::Result<std::time::Duration> {
-    let now = chrono::Local::now();
-    let day_shift = if now.naive_local().time() > target_time {
-        1
-    } else {
-        0
-    };
-
-    let new_date_time = (now + chrono::Duration::days(day_shift))
-        .date_naive()
-        .and_hms_opt(
-            target_time.hour(),
-            target_time.minute(),
-            target_time.second(),
-        )
-        .expect("should always be valid built from NaiveTime");
-
-    let result = new_date_time
-        .signed_duration_since(now.naive_local())
-        .to_std()?;
+/// Reads the responses written to an event log along with the point in time of each. Lines
+/// that cannot be parsed (for example one that was only partly written) are skipped with a warning
+pub(crate) fn read_event_log(
+    reader: impl BufRead,
+    source: &Path,
+) -> anyhow::Result<Vec<(DateTime<Local>, TimestampedResponse)>> {
+    let mut result = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {} of {source:?}", i + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response: TimestampedResponse = match serde_json::from_str(&line) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(
+                    "skipping line {} of {source:?} as it could not be parsed: {e}",
+                    i + 1
+                );
+                continue;
+            }
+        };
+        let Some(time) = response.timestamp.as_date_time() else {
+            warn!(
+                "skipping line {} of {source:?} as it has an invalid timestamp {:?}",
+                i + 1,
+                response.timestamp
+            );
+            continue;
+        };
+        result.push((time, response));
+    }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("events/2024-05-01 Google DNS events.log", Some("Google DNS"))]
+    #[case("2024-05-01 127.0.0.1 events.log", Some("127.0.0.1"))]
+    #[case("events/notes.txt", None)]
+    #[case("events/latest Google DNS events.log", None)]
+    fn target_from_file_name(#[case] path: &str, #[case] expected: Option<&str>) {
+        let actual = TargetHandler::parse_file_name(Path::new(path)).map(|(_date, target)| target);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn truncated_last_line_is_skipped() {
+        // Arrange
+        let lines = concat!(
+            r#"{"timestamp":"2024-05-01 10:00:00","response":{"Time":12}}"#,
+            "\n",
+            r#"{"timestamp":"2024-05-01 10:00:05","response":"Timeout"}"#,
+            "\n",
+            r#"{"timestamp":"2024-05-01 10:00:10","resp"#,
+        );
+
+        // Act
+        let actual = read_event_log(lines.as_bytes(), Path::new("test")).unwrap();
+
+        // Assert
+        let times: Vec<String> = actual
+            .iter()
+            .map(|(time, _)| time.format("%T").to_string())
+            .collect();
+        assert_eq!(times, ["10:00:00", "10:00:05"]);
+    }
+}
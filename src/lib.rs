@@ -1,11 +1,16 @@
 // TODO: Add option to set timeout per host
-// TODO: Add timestamp to discord messages
 mod cli;
 mod config;
 mod event_recorder;
 mod logging;
+mod mqtt;
 mod notification;
+mod outage;
 mod ping;
+mod report;
+mod schedule;
+mod secret;
+mod simulate;
 mod state_management;
 mod units;
 
@@ -15,20 +20,27 @@ use std::{
     time::Duration,
 };
 
-use crate::event_recorder::ResponseManager;
 pub(crate) use crate::{
     config::Config,
-    notification::{discord::Discord, email::Email},
     ping::{ping, Target},
     units::{Milliseconds, Seconds},
 };
-use anyhow::Context;
+use crate::{event_recorder::ResponseManager, notification::dispatcher::DryRun};
+use anyhow::{anyhow, Context};
 use event_recorder::{ResponseMessage, TargetID};
 use log::{debug, warn};
 
-pub use crate::{cli::Cli, event_recorder::TimestampedResponse};
+pub use crate::{
+    cli::{Cli, Command},
+    event_recorder::TimestampedResponse,
+};
 
 pub fn run(cli: Cli) -> anyhow::Result<()> {
+    // The error is printed on exit without going through the logs so it needs to be redacted here
+    start(cli).map_err(|e| anyhow!(secret::redact(&format!("{e:#}"))))
+}
+
+fn start(cli: Cli) -> anyhow::Result<()> {
     cli.update_current_working_dir()
         .context("failed to update current working directory")?;
     logging::init_logging(cli.log_level.into())?;
@@ -40,9 +52,32 @@ pub fn run(cli: Cli) -> anyhow::Result<()> {
     );
     let config = Config::load_from(&cli.get_config_path()).context("failed to load config")?;
 
+    match &cli.command {
+        Some(Command::NotifyTest) => return notification::notify_test::run(&config.notifications),
+        Some(Command::Simulate { paths }) => return simulate::run(&config, paths),
+        Some(Command::Outages {
+            target,
+            since,
+            json,
+        }) => return outage::run_query(target.as_deref(), *since, *json),
+        None => {}
+    }
+
+    let dry_run = match &cli.dry_run {
+        Some(path) => {
+            warn!(
+                "Dry run: notifications are written to {} instead of being sent",
+                path.as_deref()
+                    .map_or("stdout".to_string(), |x| format!("{x:?}"))
+            );
+            Some(DryRun::new(path.as_deref()).context("failed to setup dry run")?)
+        }
+        None => None,
+    };
+
     let (tx, rx) = mpsc::channel();
     let mut response_manager =
-        ResponseManager::new(rx, &config).context("failed to start response manager")?;
+        ResponseManager::new(rx, &config, dry_run).context("failed to start response manager")?;
 
     // Start up a thread for each host then await the threads
     for target in config.targets.iter().filter(|t| !t.disabled) {
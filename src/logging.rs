@@ -1,7 +1,7 @@
 // Copied and edited based on https://github.com/estk/log4rs/pull/295
 
 use anyhow::Context;
-use log::LevelFilter;
+use log::{LevelFilter, Record};
 use log4rs::Handle;
 use log4rs::{
     append::{
@@ -11,17 +11,35 @@ use log4rs::{
         },
     },
     config::{Appender, Config, Root},
-    encode::pattern::PatternEncoder,
+    encode::{pattern::PatternEncoder, writer::simple::SimpleWriter, Encode, Write},
     filter::threshold::ThresholdFilter,
 };
 
+use crate::secret;
+
+/// Wraps an encoder to remove any secrets from the output
+#[derive(Debug)]
+struct RedactingEncoder(PatternEncoder);
+
+impl Encode for RedactingEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        let mut buf = SimpleWriter(Vec::new());
+        self.0.encode(&mut buf, record)?;
+        w.write_all(secret::redact(&String::from_utf8_lossy(&buf.0)).as_bytes())?;
+        Ok(())
+    }
+}
+
 pub fn init_logging(level: LevelFilter) -> anyhow::Result<Handle> {
     let file_path = "log/file.log";
     let archive_pattern = "log/file_{}.log";
     // Pattern: https://docs.rs/log4rs/*/log4rs/append/rolling_file/policy/compound/roll/fixed_window/struct.FixedWindowRollerBuilder.html#method.build
 
     // Build a stderr logger.
-    let stderr = ConsoleAppender::builder().target(Target::Stderr).build();
+    let stderr = ConsoleAppender::builder()
+        .target(Target::Stderr)
+        .encoder(Box::new(RedactingEncoder(PatternEncoder::default())))
+        .build();
 
     // Create a policy to use with the file logging
     let trigger = SizeTrigger::new(2_097_152); // 2mb (2 * 1024 * 1024)
@@ -33,9 +51,9 @@ pub fn init_logging(level: LevelFilter) -> anyhow::Result<Handle> {
     // Logging to log file. (with rolling)
     let log_file = log4rs::append::rolling_file::RollingFileAppender::builder()
         // Pattern: https://docs.rs/log4rs/*/log4rs/encode/pattern/index.html
-        .encoder(Box::new(PatternEncoder::new(
+        .encoder(Box::new(RedactingEncoder(PatternEncoder::new(
             "{d(%Y-%m-%d %H:%M:%S)} {l} - {m}\n",
-        )))
+        ))))
         .build(file_path, Box::new(policy))
         .unwrap();
 
@@ -0,0 +1,400 @@
+use std::{thread, time::Duration};
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    event_recorder::{TargetStatus, TimestampedResponse},
+    ping::PingResponse,
+    secret::Secret,
+    state_management::{Status, VERSION},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Hostname or IP address of the broker
+    pub host: String,
+
+    #[serde(default = "MqttConfig::default_port")]
+    pub port: u16,
+
+    /// Client ID used when connecting, also identifies the device in Home Assistant
+    #[serde(default = "MqttConfig::default_client_id")]
+    pub client_id: String,
+
+    pub username: Option<String>,
+
+    pub password: Option<Secret>,
+
+    /// Prefix of the topics the status of the targets is published under
+    #[serde(default = "MqttConfig::default_topic_prefix")]
+    pub topic_prefix: String,
+
+    /// Prefix Home Assistant watches for discovery messages. Set to null to disable discovery
+    #[serde(default = "MqttConfig::default_discovery_prefix")]
+    pub discovery_prefix: Option<String>,
+}
+
+impl MqttConfig {
+    fn default_port() -> u16 {
+        1883
+    }
+
+    fn default_client_id() -> String {
+        "conn_mon".to_string()
+    }
+
+    fn default_topic_prefix() -> String {
+        "conn_mon".to_string()
+    }
+
+    fn default_discovery_prefix() -> Option<String> {
+        Some("homeassistant".to_string())
+    }
+}
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// Publishes the status of each target as retained messages so they are always available to subscribers
+pub(crate) struct MqttPublisher {
+    client: Client,
+    topic_prefix: String,
+}
+
+impl MqttPublisher {
+    /// Number of messages that can be queued while the broker is unreachable
+    const QUEUE_CAPACITY: usize = 100;
+    const KEEP_ALIVE: Duration = Duration::from_secs(30);
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+    /// Starts a thread that maintains the connection to the broker and (re)publishes the
+    /// discovery messages for `targets` each time the connection is established
+    pub(crate) fn start(config: &MqttConfig, targets: Vec<String>) -> anyhow::Result<Self> {
+        let availability_topic = availability_topic(&config.topic_prefix);
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Self::KEEP_ALIVE);
+        options.set_last_will(LastWill::new(
+            &availability_topic,
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &config.username {
+            options.set_credentials(
+                username,
+                config
+                    .password
+                    .as_ref()
+                    .map(|x| x.expose())
+                    .unwrap_or_default(),
+            );
+        }
+        let (client, mut connection) = Client::new(options, Self::QUEUE_CAPACITY);
+
+        let on_connect = client.clone();
+        // Discovery goes first so the entities exist before they are marked available
+        let mut announcements = vec![];
+        if let Some(discovery_prefix) = &config.discovery_prefix {
+            for name in targets.iter() {
+                announcements.extend(discovery_messages(discovery_prefix, config, name));
+            }
+        }
+        announcements.push((availability_topic, ONLINE.to_string()));
+        let broker = format!("{}:{}", config.host, config.port);
+        thread::Builder::new()
+            .name("MQTT".to_string())
+            .spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            info!("Connected to MQTT broker at {broker}");
+                            for (topic, payload) in announcements.iter() {
+                                try_publish(&on_connect, topic, payload.clone());
+                            }
+                        }
+                        Ok(notification) => debug!("MQTT: {notification:?}"),
+                        Err(e) => {
+                            warn!("MQTT connection to {broker} failed: {e}");
+                            thread::sleep(Self::RECONNECT_DELAY);
+                        }
+                    }
+                }
+            })
+            .context("failed to start mqtt thread")?;
+
+        Ok(Self {
+            client,
+            topic_prefix: config.topic_prefix.clone(),
+        })
+    }
+
+    /// Publishes the current state of the target and the RTT if `response` has one
+    pub(crate) fn publish(
+        &self,
+        host_disp_name: &str,
+        status: &TargetStatus,
+        response: &TimestampedResponse,
+    ) {
+        let prefix = &self.topic_prefix;
+        if let Some(state) = state_payload(status.status) {
+            try_publish(
+                &self.client,
+                &target_topic(prefix, host_disp_name, "state"),
+                state.to_string(),
+            );
+        }
+        if let PingResponse::Time(rtt) = response.response {
+            try_publish(
+                &self.client,
+                &target_topic(prefix, host_disp_name, "rtt"),
+                json!(rtt).to_string(),
+            );
+        }
+        let attributes = json!({
+            "status": status.status.to_string(),
+            "since": status.since,
+            "last_rtt_ms": status.last_rtt,
+            "last_response": response.response,
+            "timestamp": response.timestamp,
+        });
+        try_publish(
+            &self.client,
+            &target_topic(prefix, host_disp_name, "attributes"),
+            attributes.to_string(),
+        );
+    }
+}
+
+/// Does not block so the receiving of responses is never held up by the broker
+fn try_publish(client: &Client, topic: &str, payload: String) {
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload) {
+        debug!("failed to queue mqtt message for {topic:?}: {e}");
+    }
+}
+
+/// Payload for the Home Assistant binary sensor, `None` if the state of the target is not known
+fn state_payload(status: Status) -> Option<&'static str> {
+    match status {
+        Status::Up => Some("ON"),
+        Status::Down => Some("OFF"),
+        Status::Unknown | Status::SystemError => None,
+    }
+}
+
+fn availability_topic(prefix: &str) -> String {
+    format!("{prefix}/status")
+}
+
+fn target_topic(prefix: &str, host_disp_name: &str, leaf: &str) -> String {
+    format!("{prefix}/{}/{leaf}", object_id(host_disp_name))
+}
+
+/// Only characters allowed in topics and Home Assistant IDs
+fn object_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Config messages for a connectivity binary sensor and a latency sensor for the target
+fn discovery_messages(
+    discovery_prefix: &str,
+    config: &MqttConfig,
+    host_disp_name: &str,
+) -> Vec<(String, String)> {
+    let prefix = &config.topic_prefix;
+    let node_id = object_id(&config.client_id);
+    let id = object_id(host_disp_name);
+    let device = json!({
+        "identifiers": [node_id],
+        "name": config.client_id,
+        "sw_version": VERSION,
+    });
+    let connectivity = json!({
+        "name": host_disp_name,
+        "unique_id": format!("{node_id}_{id}_connectivity"),
+        "device_class": "connectivity",
+        "state_topic": target_topic(prefix, host_disp_name, "state"),
+        "json_attributes_topic": target_topic(prefix, host_disp_name, "attributes"),
+        "availability_topic": availability_topic(prefix),
+        "payload_available": ONLINE,
+        "payload_not_available": OFFLINE,
+        "device": device,
+    });
+    let latency = json!({
+        "name": format!("{host_disp_name} latency"),
+        "unique_id": format!("{node_id}_{id}_rtt"),
+        "device_class": "duration",
+        "state_class": "measurement",
+        "unit_of_measurement": "ms",
+        "state_topic": target_topic(prefix, host_disp_name, "rtt"),
+        "availability_topic": availability_topic(prefix),
+        "payload_available": ONLINE,
+        "payload_not_available": OFFLINE,
+        "device": device,
+    });
+    vec![
+        (
+            format!("{discovery_prefix}/binary_sensor/{node_id}/{id}/config"),
+            connectivity.to_string(),
+        ),
+        (
+            format!("{discovery_prefix}/sensor/{node_id}/{id}/config"),
+            latency.to_string(),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc::{self, Receiver},
+    };
+
+    use crate::event_recorder::Timestamp;
+
+    use super::*;
+
+    /// A message as received by the broker
+    #[derive(Debug)]
+    enum Received {
+        Connect(Vec<u8>),
+        Publish {
+            topic: String,
+            payload: String,
+            retain: bool,
+        },
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0; 1];
+        stream.read_exact(&mut header).ok()?;
+        let mut len = 0;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).ok()?;
+            len += ((byte[0] & 0x7F) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).ok()?;
+        Some((header[0], body))
+    }
+
+    /// Minimal stand-in for a broker that acknowledges everything and reports what it received
+    fn start_broker() -> (u16, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Some((header, body)) = read_packet(&mut stream) {
+                match header >> 4 {
+                    1 => {
+                        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+                        let _ = tx.send(Received::Connect(body));
+                    }
+                    3 => {
+                        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let topic = String::from_utf8_lossy(&body[2..2 + topic_len]).to_string();
+                        let mut rest = &body[2 + topic_len..];
+                        if (header >> 1) & 0x03 > 0 {
+                            stream.write_all(&[0x40, 0x02, rest[0], rest[1]]).unwrap();
+                            rest = &rest[2..];
+                        }
+                        let _ = tx.send(Received::Publish {
+                            topic,
+                            payload: String::from_utf8_lossy(rest).to_string(),
+                            retain: header & 0x01 == 1,
+                        });
+                    }
+                    12 => stream.write_all(&[0xD0, 0x00]).unwrap(),
+                    _ => (),
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    /// Waits for the message published to `topic` and returns its payload
+    fn published_to(rx: &Receiver<Received>, topic: &str) -> String {
+        loop {
+            match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+                Received::Publish {
+                    topic: t,
+                    payload,
+                    retain,
+                } if t == topic => {
+                    assert!(retain, "expected {topic:?} to be retained");
+                    return payload;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn publishes_discovery_and_state() {
+        // Arrange
+        let (port, rx) = start_broker();
+        let config = MqttConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            client_id: MqttConfig::default_client_id(),
+            username: None,
+            password: None,
+            topic_prefix: MqttConfig::default_topic_prefix(),
+            discovery_prefix: MqttConfig::default_discovery_prefix(),
+        };
+        let publisher = MqttPublisher::start(&config, vec!["Google DNS".to_string()]).unwrap();
+        let Received::Connect(connect) = rx.recv_timeout(Duration::from_secs(10)).unwrap() else {
+            panic!("expected connect first");
+        };
+        let discovery = published_to(
+            &rx,
+            "homeassistant/binary_sensor/conn_mon/google_dns/config",
+        );
+        assert_eq!(published_to(&rx, "conn_mon/status"), "online");
+
+        // Act
+        publisher.publish(
+            "Google DNS",
+            &TargetStatus {
+                status: Status::Up,
+                since: Timestamp::new(),
+                last_rtt: Some(12.into()),
+            },
+            &TimestampedResponse {
+                timestamp: Timestamp::new(),
+                response: PingResponse::Time(12.into()),
+            },
+        );
+
+        // Assert
+        let connect = String::from_utf8_lossy(&connect).to_string();
+        assert!(connect.contains("conn_mon/status"), "{connect:?}");
+        assert!(connect.contains(OFFLINE), "{connect:?}");
+        let discovery: serde_json::Value = serde_json::from_str(&discovery).unwrap();
+        assert_eq!(discovery["state_topic"], "conn_mon/google_dns/state");
+        assert_eq!(discovery["device_class"], "connectivity");
+        assert_eq!(published_to(&rx, "conn_mon/google_dns/state"), "ON");
+        assert_eq!(published_to(&rx, "conn_mon/google_dns/rtt"), "12");
+    }
+}
@@ -1,2 +1,465 @@
 pub(crate) mod discord;
+pub(crate) mod dispatcher;
 pub(crate) mod email;
+pub(crate) mod exec;
+pub(crate) mod journal;
+pub(crate) mod matrix;
+pub(crate) mod notify_test;
+pub(crate) mod pagerduty;
+pub(crate) mod push;
+pub(crate) mod routing;
+pub(crate) mod slack;
+pub(crate) mod syslog;
+pub(crate) mod telegram;
+pub(crate) mod template;
+pub(crate) mod webhook;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_recorder::EventMessage,
+    outage::Outage,
+    state_management::{EventKind, Severity, VERSION},
+};
+
+use self::{
+    discord::{Discord, DiscordConfig},
+    dispatcher::{BatchingConfig, QuietHoursConfig},
+    email::EmailConfig,
+    exec::{Exec, ExecConfig},
+    matrix::{Matrix, MatrixConfig},
+    pagerduty::{PagerDuty, PagerDutyConfig},
+    push::{Push, PushConfig},
+    routing::RouteConfig,
+    slack::{Slack, SlackConfig},
+    syslog::{Syslog, SyslogConfig},
+    telegram::{Telegram, TelegramConfig},
+    template::TemplateConfig,
+    webhook::{Webhook, WebhookConfig},
+};
+
+/// The kinds of channels notifications can be sent via
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Discord,
+    Email,
+    Webhook,
+    Slack,
+    Telegram,
+    Push,
+    Matrix,
+    Exec,
+    Syslog,
+    #[serde(rename = "pagerduty")]
+    PagerDuty,
+}
+
+/// A channel that is sent every event, independent of discord and email
+pub(crate) trait Notifier: Send {
+    /// Name used to identify the channel in logs
+    fn name(&self) -> &str;
+
+    /// The kind of channel this notifier sends via
+    fn channel(&self) -> Channel;
+
+    /// If true events are always sent one at a time instead of being combined into digests
+    fn needs_each_event(&self) -> bool {
+        false
+    }
+
+    /// If events of this kind are sent via this channel, the rest are skipped. Notifiers that
+    /// skip some kinds also need each event so a digest never includes skipped events
+    fn accepts(&self, _kind: EventKind) -> bool {
+        true
+    }
+
+    /// Attempts to deliver the event via this channel
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Settings for discord notifications, disabled if not set
+    pub discord: Option<DiscordConfig>,
+
+    /// SMTP settings for email notifications, disabled if not set
+    pub email: Option<EmailConfig>,
+
+    /// Generic webhooks that get a JSON payload posted for each event
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    /// Slack incoming webhooks to post each event to
+    #[serde(default)]
+    pub slack: Vec<SlackConfig>,
+
+    /// Telegram bots to send each event with
+    #[serde(default)]
+    pub telegram: Vec<TelegramConfig>,
+
+    /// Self hosted push services (ntfy or gotify) to publish each event to
+    #[serde(default)]
+    pub push: Vec<PushConfig>,
+
+    /// Matrix rooms to send each event to
+    #[serde(default)]
+    pub matrix: Vec<MatrixConfig>,
+
+    /// Local commands to run for events
+    #[serde(default)]
+    pub exec: Vec<ExecConfig>,
+
+    /// Local journald or syslog sinks to write each event to
+    #[serde(default)]
+    pub syslog: Vec<SyslogConfig>,
+
+    /// PagerDuty (or compatible) services to open incidents on when a target goes down
+    #[serde(default)]
+    pub pagerduty: Vec<PagerDutyConfig>,
+
+    /// If set events close together are grouped into a single digest message
+    pub batching: Option<BatchingConfig>,
+
+    /// Quiet hours for specific channels, during which only urgent events are sent immediately
+    #[serde(default)]
+    pub quiet_hours: BTreeMap<Channel, QuietHoursConfig>,
+
+    /// Rules that send the events of specific targets only to specific channels
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+
+    /// Custom wording for notifications
+    #[serde(default)]
+    pub templates: TemplateConfig,
+}
+
+impl NotificationConfig {
+    /// Fails if settings are still in the files in `folder` that were used before discord and
+    /// email were set in the config, as they would otherwise silently stop being sent
+    pub(crate) fn check_legacy_files(&self, folder: &Path) -> anyhow::Result<()> {
+        let discord_file = folder.join(Discord::LEGACY_FILENAME);
+        if self.discord.is_none() && discord_file.exists() {
+            bail!(
+                "discord settings found in {discord_file:?} which is no longer read. Move them into \
+                the config as \"notifications\": {{\"discord\": {{\"webhook_url\": {{\"file\": {:?}}}}}}} \
+                or delete the file to disable discord notifications",
+                Discord::LEGACY_FILENAME
+            );
+        }
+        let email_file = folder.join(EmailConfig::LEGACY_FILENAME);
+        if self.email.is_none() && email_file.exists() {
+            bail!(
+                "email settings found in {email_file:?} which is no longer read. Move its contents \
+                into the config as \"notifications\": {{\"email\": <contents of the file>}} or \
+                delete the file to disable email notifications"
+            );
+        }
+        Ok(())
+    }
+
+    /// Builds all the configured notifiers, those that fail to build are logged and skipped
+    pub(crate) fn build_notifiers(&self) -> Vec<Box<dyn Notifier>> {
+        self.try_build_notifiers()
+            .into_iter()
+            .filter_map(|(channel, notifier)| match notifier {
+                Ok(notifier) => Some(notifier),
+                Err(e) => {
+                    error!("Unable to setup {channel}. It will be disabled. Error: {e:?}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Builds all the configured notifiers along with the name of the channel of each
+    pub(crate) fn try_build_notifiers(
+        &self,
+    ) -> Vec<(&'static str, anyhow::Result<Box<dyn Notifier>>)> {
+        let mut result = vec![];
+        build_each(&mut result, "webhook", &self.webhooks, Webhook::new);
+        build_each(&mut result, "slack", &self.slack, Slack::new);
+        build_each(&mut result, "telegram", &self.telegram, Telegram::new);
+        build_each(&mut result, "push", &self.push, Push::new);
+        build_each(&mut result, "matrix", &self.matrix, Matrix::new);
+        build_each(&mut result, "exec", &self.exec, Exec::new);
+        build_each(&mut result, "syslog", &self.syslog, Syslog::new);
+        build_each(&mut result, "pagerduty", &self.pagerduty, PagerDuty::new);
+        result
+    }
+}
+
+fn build_each<C, N: Notifier + 'static>(
+    result: &mut Vec<(&'static str, anyhow::Result<Box<dyn Notifier>>)>,
+    channel: &'static str,
+    configs: &[C],
+    build: fn(&C) -> anyhow::Result<N>,
+) {
+    for config in configs {
+        let notifier = build(config).map(|x| Box::new(x) as Box<dyn Notifier>);
+        result.push((channel, notifier));
+    }
+}
+
+/// Machine readable representation of an event used by channels that integrate with other tools
+#[derive(Debug, Serialize)]
+pub(crate) struct EventPayload<'a> {
+    target: &'a str,
+    event: EventKind,
+    severity: Severity,
+    message: String,
+    duration_secs: Option<u64>,
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outage: Option<&'a Outage>,
+    timestamp: String,
+    version: &'a str,
+}
+
+impl<'a> From<&'a EventMessage> for EventPayload<'a> {
+    fn from(value: &'a EventMessage) -> Self {
+        Self {
+            target: &value.host_disp_name,
+            event: value.event.kind(),
+            severity: value.event.kind().severity(),
+            message: value.text.clone(),
+            duration_secs: value.event.duration().map(|x| x.as_u64()),
+            error: value.event.error_msg(),
+            outage: value.event.outage(),
+            timestamp: value.timestamp.to_string(),
+            version: VERSION,
+        }
+    }
+}
+
+/// Color (as 0xRRGGBB) used to highlight messages of this kind in channels that support it
+pub(crate) fn event_color(kind: EventKind) -> u32 {
+    match kind {
+        EventKind::Startup
+        | EventKind::IAmAlive
+        | EventKind::Report
+        | EventKind::Digest
+        | EventKind::Test => 0x1E88E5, // Blue
+        EventKind::ConnectionRestoredAfter => 0x43A047, // Green
+        EventKind::ConnectionStillDown => 0xFB8C00,     // Orange
+        EventKind::ConnectionFailed | EventKind::ConnectionError => 0xE53935, // Red
+        EventKind::SystemError | EventKind::StillSystemError => 0x8E24AA, // Purple
+    }
+}
+
+/// Short user facing description of the state the target is in after an event of this kind
+pub(crate) fn state_label(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Startup | EventKind::IAmAlive => "Info",
+        EventKind::Report => "Report",
+        EventKind::Test => "Test",
+        EventKind::ConnectionRestoredAfter => "Up",
+        EventKind::ConnectionFailed | EventKind::ConnectionError => "Down",
+        EventKind::ConnectionStillDown => "Still Down",
+        EventKind::SystemError | EventKind::StillSystemError => "System Error",
+        EventKind::Digest => "Digest",
+    }
+}
+
+/// Tracks the message that started each target's current outage so that reminders and the
+/// restore can be sent as replies to it, keeping each outage in one conversation
+#[derive(Debug, Default)]
+pub(crate) struct OutageThreads(Mutex<HashMap<String, String>>);
+
+impl OutageThreads {
+    /// Calls `send` with the ID of the message to reply to (if any) and records the ID it
+    /// returns if the event starts a new outage
+    pub(crate) fn send<F>(&self, event_msg: &EventMessage, send: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(Option<&str>) -> anyhow::Result<String>,
+    {
+        let mut roots = self
+            .0
+            .lock()
+            .map_err(|e| anyhow!("outage threads lock poisoned: {e}"))?;
+        let target = &event_msg.host_disp_name;
+        match event_msg.event.kind() {
+            EventKind::ConnectionFailed | EventKind::ConnectionError | EventKind::SystemError => {
+                let id = send(None)?;
+                roots.insert(target.clone(), id);
+            }
+            EventKind::ConnectionStillDown | EventKind::StillSystemError => {
+                send(roots.get(target).map(String::as_str))?;
+            }
+            EventKind::ConnectionRestoredAfter => {
+                send(roots.get(target).map(String::as_str))?;
+                roots.remove(target);
+            }
+            EventKind::Startup
+            | EventKind::IAmAlive
+            | EventKind::Report
+            | EventKind::Digest
+            | EventKind::Test => {
+                send(None)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+}
+
+/// Escapes text so it can be safely included in HTML
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc::{self, Receiver},
+        thread,
+    };
+
+    /// A request as received by the [`start`] stand-in server
+    #[derive(Debug)]
+    pub(crate) struct ReceivedRequest {
+        pub(crate) request_line: String,
+        pub(crate) headers: Vec<(String, String)>,
+        pub(crate) body: String,
+    }
+
+    impl ReceivedRequest {
+        pub(crate) fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// A canned response for the [`start`] stand-in server to reply with
+    pub(crate) struct TestResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl TestResponse {
+        pub(crate) fn new(status: u16, body: &str) -> Self {
+            Self {
+                status,
+                headers: vec![],
+                body: body.to_string(),
+            }
+        }
+
+        pub(crate) fn ok() -> Self {
+            Self::new(200, "")
+        }
+
+        pub(crate) fn with_header(mut self, key: &str, value: &str) -> Self {
+            self.headers.push((key.to_string(), value.to_string()));
+            self
+        }
+    }
+
+    /// Starts a local HTTP server that replies to each request with the next of `responses`
+    /// and passes on what it received. Returns the base url of the server.
+    pub(crate) fn start(responses: Vec<TestResponse>) -> (String, Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().expect("failed to accept connection");
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (key, value) = line.split_once(':').unwrap();
+                    headers.push((key.trim().to_string(), value.trim().to_string()));
+                }
+                let content_length = headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+                    .map(|(_, value)| value.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let mut stream = reader.into_inner();
+                let TestResponse {
+                    status,
+                    headers: response_headers,
+                    body: response_body,
+                } = response;
+                let mut head = format!(
+                    "HTTP/1.1 {status} Test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response_body.len()
+                );
+                for (key, value) in response_headers {
+                    head.push_str(&format!("{key}: {value}\r\n"));
+                }
+                write!(stream, "{head}\r\n{response_body}").unwrap();
+                let _ = tx.send(ReceivedRequest {
+                    request_line: request_line.trim_end().to_string(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+            }
+        });
+        (url, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_files_without_config_are_error() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let config = NotificationConfig::default();
+        let before = config.check_legacy_files(dir.path());
+        std::fs::write(dir.path().join(Discord::LEGACY_FILENAME), "0000/token").unwrap();
+
+        // Act
+        let actual = config.check_legacy_files(dir.path());
+
+        // Assert
+        assert!(before.is_ok(), "{before:?}");
+        let err = actual.unwrap_err().to_string();
+        assert!(
+            err.contains(r#""webhook_url": {"file": "d.data"}"#),
+            "{err}"
+        );
+    }
+}
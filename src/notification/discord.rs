@@ -1,16 +1,37 @@
-use std::fs;
+use std::sync::OnceLock;
 
-use anyhow::{bail, Context};
-use log::{error, warn};
-use serenity::{builder::ExecuteWebhook, http::Http, model::webhook::Webhook};
+use anyhow::{anyhow, bail, Context};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::{CreateEmbed, CreateEmbedFooter, ExecuteWebhook},
+    http::{Http, HttpError, RatelimitInfo},
+    model::{webhook::Webhook, Timestamp},
+};
 use tokio::runtime::Runtime;
 
-use crate::Seconds;
+use crate::{event_recorder::EventMessage, secret::Secret, state_management::VERSION, Seconds};
+
+use super::event_color;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    /// Url of the webhook, or only the part after `https://discord.com/api/webhooks/`
+    pub webhook_url: Secret,
+
+    /// If true messages are sent as plain text instead of embeds
+    #[serde(default)]
+    pub plain_text: bool,
+}
 
 pub struct Discord {
     rt: Runtime,
     http: Http,
     url: String,
+    plain_text: bool,
+    /// Fetched once and then reused for every message
+    webhook: OnceLock<Webhook>,
 }
 
 impl Discord {
@@ -18,18 +39,51 @@ impl Discord {
     const RETRY_ATTEMPTS: u8 = 3;
     const INTERVAL_BETWEEN_RETRY: Seconds = Seconds::new(15);
 
-    pub fn new() -> anyhow::Result<Self> {
-        let filename = "d.data";
-        let url_suffix = fs::read_to_string(filename).with_context(|| {
-            format!("failed to read discord webhook url suffix from {filename:?}")
-        })?;
-        let url = format!("https://discord.com/api/webhooks/{url_suffix}");
+    const WEBHOOK_BASE_URL: &'static str = "https://discord.com/api/webhooks/";
+
+    /// File the webhook url suffix was read from before it was part of the config
+    pub(crate) const LEGACY_FILENAME: &'static str = "d.data";
+
+    pub fn new(config: &DiscordConfig) -> anyhow::Result<Self> {
+        let webhook_url = config.webhook_url.expose().trim();
+        let url = if webhook_url.starts_with("https://") {
+            webhook_url.to_string()
+        } else {
+            format!("{}{webhook_url}", Self::WEBHOOK_BASE_URL)
+        };
         let rt = tokio::runtime::Runtime::new().context("failed to create async runtime")?;
-        let http = Http::new("");
-        Ok(Self { rt, http, url })
+        let mut http = Http::new("");
+        if let Some(ratelimiter) = http.ratelimiter.as_mut() {
+            // Serenity waits for `retry_after` itself, this is only so it is visible in the logs
+            ratelimiter.set_ratelimit_callback(Box::new(|info: RatelimitInfo| {
+                warn!(
+                    "discord rate limit hit (global: {}), waiting {:?} before sending",
+                    info.global, info.timeout
+                );
+            }));
+        }
+        Ok(Self {
+            rt,
+            http,
+            url,
+            plain_text: config.plain_text,
+            webhook: OnceLock::new(),
+        })
     }
 
-    pub fn send(&self, msg: &str) -> anyhow::Result<()> {
+    /// Fetches the webhook so problems are found at startup. Only fails if the webhook is
+    /// unusable, other errors are logged and it is fetched again on the first message
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Err(e) = self.webhook() {
+            if Self::is_permanent(&e) {
+                return Err(e);
+            }
+            warn!("unable to validate discord webhook, will try again on first message: {e:?}");
+        }
+        Ok(())
+    }
+
+    pub fn send(&self, event_msg: &EventMessage, msg: &str) -> anyhow::Result<()> {
         warn!("DISCORD MESSAGE: {msg}");
         for i in 0..Self::RETRY_ATTEMPTS {
             // Wait before trying again
@@ -37,12 +91,11 @@ impl Discord {
                 std::thread::sleep(Self::INTERVAL_BETWEEN_RETRY.into());
             }
 
-            match self
-                .rt
-                .block_on(self.do_send(msg))
-                .context("failed to send ")
-            {
+            match self.do_send(event_msg, msg) {
                 Ok(()) => return Ok(()),
+                Err(e) if Self::is_permanent(&e) => {
+                    return Err(e.context("failed to send via discord, not retrying"))
+                }
                 Err(e) => error!(
                     "attempt #{} failed to send via discord. Error: {e:?}",
                     i + 1
@@ -55,15 +108,157 @@ impl Discord {
         )
     }
 
-    async fn do_send(&self, msg: &str) -> anyhow::Result<()> {
-        let webhook = Webhook::from_url(&self.http, &self.url)
-            .await
-            .context("failed to build webhook")?;
-        let builder = ExecuteWebhook::new().content(msg);
-        webhook
-            .execute(&self.http, true, builder)
-            .await
-            .context("failed to send msg via discord using webhook")?;
+    /// Returns the cached webhook, fetching (and thereby validating) it if not fetched yet
+    fn webhook(&self) -> anyhow::Result<&Webhook> {
+        if let Some(webhook) = self.webhook.get() {
+            return Ok(webhook);
+        }
+        let webhook = self
+            .rt
+            .block_on(Webhook::from_url(&self.http, &self.url))
+            .map_err(|e| Self::describe_error(e, "failed to fetch discord webhook"))?;
+        info!(
+            "discord webhook validated. Name: {:?}",
+            webhook.name.as_deref().unwrap_or_default()
+        );
+        Ok(self.webhook.get_or_init(|| webhook))
+    }
+
+    /// Adds an explanation to errors that indicate the webhook itself is unusable
+    fn describe_error(err: serenity::Error, context: &'static str) -> anyhow::Error {
+        let permanent = match &err {
+            serenity::Error::Http(HttpError::Url(_) | HttpError::InvalidWebhook) => Some(
+                "the webhook url is malformed, check the discord webhook_url setting".to_string(),
+            ),
+            serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+                Self::webhook_rejection(response.status_code.as_u16(), response.error.code)
+            }
+            _ => None,
+        };
+        match permanent {
+            Some(reason) => anyhow!(PermanentError(reason)).context(err.to_string()),
+            None => anyhow::Error::new(err).context(context),
+        }
+    }
+
+    /// Explains responses from discord that mean retrying will not help
+    ///
+    /// See https://discord.com/developers/docs/topics/opcodes-and-status-codes#json
+    fn webhook_rejection(status: u16, code: isize) -> Option<String> {
+        match (status, code) {
+            (_, 10015) | (404, _) => {
+                Some("the webhook does not exist, it may have been deleted".to_string())
+            }
+            (_, 50027) | (401, _) => Some("the webhook token is invalid".to_string()),
+            (403, _) => Some("the webhook is not permitted to post to its channel".to_string()),
+            _ => None,
+        }
+    }
+
+    fn is_permanent(err: &anyhow::Error) -> bool {
+        err.downcast_ref::<PermanentError>().is_some()
+    }
+
+    fn build_embed(event_msg: &EventMessage) -> CreateEmbed {
+        let EventMessage {
+            host_disp_name: name,
+            timestamp,
+            event,
+            text,
+            ..
+        } = event_msg;
+        let mut result = CreateEmbed::new()
+            .title(name)
+            .description(text)
+            .colour(event_color(event.kind()))
+            .field("Target", name, true)
+            .footer(CreateEmbedFooter::new(format!("conn_mon {VERSION}")));
+        if let Some(duration) = event.duration() {
+            result = result.field("Duration", duration.to_string(), true);
+        }
+        if let Some(err_msg) = event.error_msg() {
+            result = result.field("Last Error", err_msg, false);
+        }
+        let embed_timestamp = timestamp
+            .as_date_time()
+            .and_then(|x| Timestamp::from_unix_timestamp(x.timestamp()).ok());
+        if let Some(embed_timestamp) = embed_timestamp {
+            result = result.timestamp(embed_timestamp);
+        }
+        result
+    }
+
+    fn do_send(&self, event_msg: &EventMessage, msg: &str) -> anyhow::Result<()> {
+        let webhook = self.webhook()?;
+        let builder = if self.plain_text {
+            ExecuteWebhook::new().content(msg)
+        } else {
+            ExecuteWebhook::new().embed(Self::build_embed(event_msg))
+        };
+        self.rt
+            .block_on(webhook.execute(&self.http, true, builder))
+            .map_err(|e| Self::describe_error(e, "failed to send msg via discord using webhook"))?;
         Ok(())
     }
 }
+
+/// An error that will not be resolved by retrying
+#[derive(Debug)]
+struct PermanentError(String);
+
+impl std::fmt::Display for PermanentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PermanentError {}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::state_management::Event;
+
+    use super::*;
+
+    #[test]
+    fn embed_has_fields() {
+        let event_msg = EventMessage::new(
+            "Google DNS".to_string(),
+            Event::ConnectionError(45.into(), "Destination Host Unreachable".to_string()),
+        );
+
+        let actual = serde_json::to_value(Discord::build_embed(&event_msg)).unwrap();
+
+        assert_eq!(actual["color"], 0xE53935);
+        assert_eq!(actual["fields"][1]["name"], "Duration");
+        assert_eq!(actual["fields"][1]["value"], "0 days 00:00:45");
+        assert_eq!(actual["fields"][2]["value"], "Destination Host Unreachable");
+        assert_eq!(actual["footer"]["text"], format!("conn_mon {VERSION}"));
+        assert!(actual["timestamp"].is_string());
+    }
+
+    #[rstest]
+    #[case::deleted(404, 10015, true)]
+    #[case::bad_token(401, 50027, true)]
+    #[case::missing_access(403, 50001, true)]
+    #[case::server_error(502, 0, false)]
+    #[case::rate_limited(429, 0, false)]
+    fn webhook_rejection(#[case] status: u16, #[case] code: isize, #[case] expected: bool) {
+        let actual = Discord::webhook_rejection(status, code);
+
+        assert_eq!(actual.is_some(), expected, "{actual:?}");
+    }
+
+    #[test]
+    fn malformed_url_is_permanent() {
+        let err = Discord::describe_error(
+            serenity::Error::Http(HttpError::InvalidWebhook),
+            "failed to fetch discord webhook",
+        );
+
+        assert!(Discord::is_permanent(&err));
+        assert!(format!("{err:?}").contains("webhook_url"), "{err:?}");
+    }
+}
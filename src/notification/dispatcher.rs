@@ -0,0 +1,1122 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{Local, NaiveTime};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    event_recorder::{EventMessage, StatusBoard, TargetHandler},
+    schedule::Schedule,
+    state_management::{Event, EventKind, Severity},
+    Seconds,
+};
+
+use super::{
+    discord::Discord,
+    email::Email,
+    journal::{Deliveries, Journal, Outcome},
+    routing::{Route, RouteConfig},
+    template::TemplateConfig,
+    Channel, Notifier,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BatchingConfig {
+    /// Events that arrive within this time of the first one are sent together as a digest
+    pub window: Seconds,
+
+    /// Kinds of events that are sent immediately instead of being batched (Startup always is)
+    #[serde(default)]
+    pub bypass: Vec<EventKind>,
+}
+
+impl BatchingConfig {
+    fn is_bypassed(&self, kind: EventKind) -> bool {
+        kind == EventKind::Startup || self.bypass.contains(&kind)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHoursConfig {
+    /// Local time quiet hours start
+    pub start: NaiveTime,
+
+    /// Local time quiet hours end, events held during quiet hours are sent as a summary then
+    pub end: NaiveTime,
+
+    /// Events of at least this severity are still sent immediately during quiet hours
+    #[serde(default = "QuietHoursConfig::default_min_severity")]
+    pub min_severity: Severity,
+}
+
+impl QuietHoursConfig {
+    fn default_min_severity() -> Severity {
+        Severity::High
+    }
+
+    /// Quiet hours may wrap past midnight (eg. 22:00 to 07:00)
+    fn is_active(&self, now: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= now && now < self.end
+        } else {
+            self.start <= now || now < self.end
+        }
+    }
+
+    fn should_hold(&self, kind: EventKind, now: NaiveTime) -> bool {
+        self.is_active(now) && kind.severity() < self.min_severity
+    }
+}
+
+/// Where a message is sent, only email supports choosing recipients
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+struct Destination {
+    channel: Channel,
+    email_to: Option<Vec<String>>,
+}
+
+impl Destination {
+    fn new(channel: Channel) -> Self {
+        Self {
+            channel,
+            email_to: None,
+        }
+    }
+
+    /// Name used for the destination in output and the journal
+    fn label(&self) -> String {
+        let channel = format!("{:?}", self.channel).to_lowercase();
+        match &self.email_to {
+            Some(to) => format!("{channel} (to: {})", to.join(", ")),
+            None => channel,
+        }
+    }
+}
+
+/// Where notifications are written instead of being sent when doing a dry run
+pub(crate) struct DryRun(RefCell<Box<dyn Write + Send>>);
+
+impl DryRun {
+    /// Appends to the file at `path` or writes to stdout if not set
+    pub(crate) fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let output: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open dry run output file {path:?}"))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+        Ok(Self(RefCell::new(output)))
+    }
+
+    /// Records that the message would have been sent via `channel`
+    fn write(&self, channel: &str, event_msg: &EventMessage) -> Outcome {
+        let mut output = self.0.borrow_mut();
+        let result = writeln!(
+            output,
+            "[dry run] {channel}: {}",
+            event_msg.message.replace('\n', "\n    ")
+        )
+        .and_then(|_| output.flush());
+        match result {
+            Ok(()) => Outcome::DryRun,
+            Err(e) => {
+                error!("failed to write dry run output: {e}");
+                Outcome::Failed(format!("failed to write dry run output: {e}"))
+            }
+        }
+    }
+}
+
+/// Sends events to all the configured channels
+pub(crate) struct Dispatcher {
+    discord: Option<Discord>,
+    email: Option<Email>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    templates: TemplateConfig,
+    batching: Option<BatchingConfig>,
+    quiet_hours: BTreeMap<Channel, QuietHoursConfig>,
+    /// Events not sent because of quiet hours, to be included in the summary sent after
+    held: RefCell<BTreeMap<Destination, Vec<EventMessage>>>,
+    routes: Vec<RouteConfig>,
+    /// Tags of each target by display name
+    target_tags: HashMap<String, Vec<String>>,
+    status_board: StatusBoard,
+    /// If set nothing is sent, the messages are written to it instead
+    dry_run: Option<DryRun>,
+    /// Where the delivery outcome of each event is recorded
+    journal: Option<Journal>,
+}
+
+/// Messages to send via a channel along with the events each one includes
+struct ChannelMessages<'a> {
+    held: Vec<&'a EventMessage>,
+    messages: Vec<(Vec<&'a EventMessage>, EventMessage)>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(config: &Config, status_board: StatusBoard, dry_run: Option<DryRun>) -> Self {
+        let discord = config.notifications.discord.as_ref().map(|discord_config| {
+            let discord = Discord::new(discord_config)?;
+            if dry_run.is_none() {
+                discord.validate()?;
+            }
+            anyhow::Ok(discord)
+        });
+        let discord: Option<Discord> = match discord {
+            Some(Ok(d)) => Some(d),
+            Some(Err(e)) => {
+                error!(
+                    "Unable to setup discord. Discord notifications will be disabled.Error:\n{e:?}"
+                );
+                None
+            }
+            None => {
+                warn!("Discord not configured. Discord notifications are disabled");
+                None
+            }
+        };
+        let email: Option<Email> = match config.notifications.email.as_ref().map(Email::new) {
+            Some(Ok(client)) => Some(client),
+            Some(Err(e)) => {
+                error!("Unable to setup email. Email notifications will be disabled. {e:?}");
+                None
+            }
+            None => {
+                warn!("Email not configured. Email notifications are disabled");
+                None
+            }
+        };
+        Self {
+            discord,
+            email,
+            notifiers: config.notifications.build_notifiers(),
+            templates: config.notifications.templates.clone(),
+            batching: config.notifications.batching.clone(),
+            quiet_hours: config.notifications.quiet_hours.clone(),
+            held: Default::default(),
+            routes: config.notifications.routes.clone(),
+            target_tags: config
+                .targets
+                .iter()
+                .map(|target| (target.to_string(), target.tags.clone()))
+                .collect(),
+            status_board,
+            dry_run,
+            journal: Some(Journal::new(TargetHandler::BASE_FOLDER)),
+        }
+    }
+
+    /// Sends events as they are received until all senders are dropped
+    pub(crate) fn run(&self, rx: Receiver<EventMessage>) {
+        loop {
+            let received = match self.time_until_next_summary() {
+                Some(timeout) => rx.recv_timeout(timeout),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            self.send_due_summaries(Local::now().time());
+            let event_message = match received {
+                Ok(event_message) => event_message,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            match &self.batching {
+                Some(batching) if !batching.is_bypassed(event_message.event.kind()) => {
+                    let batch = self.collect_batch(&rx, batching, event_message);
+                    self.dispatch_batch(batch);
+                }
+                _ => self.dispatch(&event_message),
+            }
+        }
+        info!("all event senders dropped, event dispatch stopped");
+    }
+
+    /// Collects events until the window after `first` is over, bypassed events are sent immediately
+    fn collect_batch(
+        &self,
+        rx: &Receiver<EventMessage>,
+        batching: &BatchingConfig,
+        first: EventMessage,
+    ) -> Vec<EventMessage> {
+        let deadline = Instant::now() + Duration::from(batching.window);
+        let mut result = vec![first];
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(timeout) {
+                Ok(event_message) if batching.is_bypassed(event_message.event.kind()) => {
+                    self.dispatch(&event_message)
+                }
+                Ok(event_message) => result.push(event_message),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        result
+    }
+
+    fn dispatch_batch(&self, mut batch: Vec<EventMessage>) {
+        debug!("sending batch of {} events", batch.len());
+        batch.sort_by(|a, b| {
+            (&a.host_disp_name, a.event.kind()).cmp(&(&b.host_disp_name, b.event.kind()))
+        });
+        self.send_to_all(&batch, false, Local::now().time());
+    }
+
+    fn dispatch(&self, event_message: &EventMessage) {
+        self.send_to_all(
+            std::slice::from_ref(event_message),
+            event_message.event == Event::Startup,
+            Local::now().time(),
+        );
+    }
+
+    /// Builds a single message with each event as rendered for `channel` on its own line
+    fn build_digest(&self, channel: Channel, events: &[&EventMessage]) -> EventMessage {
+        let mut result = EventMessage::system_message(Event::Digest(events.len()));
+        for event_message in events {
+            let rendered = self.templates.render(channel, event_message);
+            result.text.push_str(&format!("\n- {}", rendered.message));
+        }
+        result.message = EventMessage::default_message(&result);
+        result
+    }
+
+    fn route(&self, event_message: &EventMessage) -> Route {
+        let tags = self
+            .target_tags
+            .get(&event_message.host_disp_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        Route::find(
+            &self.routes,
+            &event_message.host_disp_name,
+            tags,
+            event_message.event.kind(),
+        )
+    }
+
+    /// Holds the events that should wait for the end of quiet hours on the channel and returns
+    /// those held and the rest
+    fn hold<'a>(
+        &self,
+        destination: &Destination,
+        events: &[&'a EventMessage],
+        now: NaiveTime,
+    ) -> (Vec<&'a EventMessage>, Vec<&'a EventMessage>) {
+        let channel = destination.channel;
+        let quiet_hours = self.quiet_hours.get(&channel);
+        let (held, to_send): (Vec<&EventMessage>, Vec<&EventMessage>) =
+            events.iter().partition(|event_message| {
+                quiet_hours.is_some_and(|x| x.should_hold(event_message.event.kind(), now))
+            });
+        if !held.is_empty() {
+            debug!(
+                "holding {} events for {channel:?} until quiet hours end",
+                held.len()
+            );
+            self.held
+                .borrow_mut()
+                .entry(destination.clone())
+                .or_default()
+                .extend(held.iter().copied().cloned());
+        }
+        (held, to_send)
+    }
+
+    /// The message for `channel` with all the events, a digest if there is more than one
+    fn combine(&self, channel: Channel, events: &[&EventMessage]) -> Option<EventMessage> {
+        match events {
+            [] => None,
+            [event_message] => Some(self.templates.render(channel, event_message)),
+            _ => Some(self.build_digest(channel, events)),
+        }
+    }
+
+    /// Sends to every notifier and then discord with email as a fallback (or both if `test_all`).
+    /// Events that match a routing rule only go to the channels the rule lists.
+    fn send_to_all(&self, events: &[EventMessage], test_all: bool, now: NaiveTime) {
+        let routed: Vec<(&EventMessage, Route)> = events
+            .iter()
+            .map(|event_message| (event_message, self.route(event_message)))
+            .collect();
+        let allowed = |channel| -> Vec<&EventMessage> {
+            routed
+                .iter()
+                .filter(|(_, route)| route.allows(channel))
+                .map(|(event_message, _)| *event_message)
+                .collect()
+        };
+
+        let mut deliveries = Deliveries::new(events);
+
+        let mut prepared: HashMap<Channel, ChannelMessages> = HashMap::new();
+        for notifier in self.notifiers.iter() {
+            let channel = notifier.channel();
+            let prepared = prepared.entry(channel).or_insert_with(|| {
+                let (held, to_send) = self.hold(&Destination::new(channel), &allowed(channel), now);
+                let messages = if notifier.needs_each_event() {
+                    to_send
+                        .into_iter()
+                        .map(|event_message| {
+                            (
+                                vec![event_message],
+                                self.templates.render(channel, event_message),
+                            )
+                        })
+                        .collect()
+                } else {
+                    self.combine(channel, &to_send)
+                        .map(|event_msg| (to_send, event_msg))
+                        .into_iter()
+                        .collect()
+                };
+                ChannelMessages { held, messages }
+            });
+            let held = skip_unaccepted(notifier.as_ref(), &prepared.held, &mut deliveries);
+            deliveries.record(&held, notifier.name(), &Outcome::Held);
+            for (included, event_msg) in prepared.messages.iter() {
+                let included = skip_unaccepted(notifier.as_ref(), included, &mut deliveries);
+                if included.is_empty() {
+                    continue;
+                }
+                let outcome = self.send_via_notifier(notifier.as_ref(), event_msg);
+                deliveries.record(&included, notifier.name(), &outcome);
+            }
+        }
+
+        // Held messages count as handled so they do not fall back to another channel
+        let handled_by_discord = match &self.discord {
+            Some(_) => self
+                .send_to_destination(
+                    &Destination::new(Channel::Discord),
+                    &allowed(Channel::Discord),
+                    now,
+                    &mut deliveries,
+                )
+                .is_handled(),
+            None => false,
+        };
+        if test_all && self.discord.is_some() && !handled_by_discord {
+            error!("Test of discord failed");
+        }
+
+        let use_email_by_default = test_all || !handled_by_discord;
+        let mut for_email: BTreeMap<Destination, Vec<&EventMessage>> = BTreeMap::new();
+        for (event_message, route) in routed.iter() {
+            if route.is_explicit(Channel::Email) || (route.is_default() && use_email_by_default) {
+                let destination = Destination {
+                    channel: Channel::Email,
+                    email_to: route.email_to().map(<[String]>::to_vec),
+                };
+                for_email
+                    .entry(destination)
+                    .or_default()
+                    .push(event_message);
+            }
+        }
+        let mut handled_by_email = self.email.is_some();
+        if self.email.is_some() {
+            for (destination, events) in for_email {
+                handled_by_email &= self
+                    .send_to_destination(&destination, &events, now, &mut deliveries)
+                    .is_handled();
+            }
+        }
+        if test_all && self.email.is_some() && !handled_by_email {
+            error!("Test of email failed");
+        }
+
+        let has_default_route = routed.iter().any(|(_, route)| route.is_default());
+        if !test_all && has_default_route && !handled_by_discord && !handled_by_email {
+            error!("failed to send notification via all means. Events were: {events:?}");
+        }
+        self.write_journal(&deliveries);
+    }
+
+    /// Sends the events not held as one message via discord or email and records the outcomes.
+    /// Returns the outcome of the message sent, or held or skipped if nothing was left to send
+    fn send_to_destination<'a>(
+        &self,
+        destination: &Destination,
+        events: &[&'a EventMessage],
+        now: NaiveTime,
+        deliveries: &mut Deliveries<'a>,
+    ) -> Outcome {
+        let label = destination.label();
+        let (held, to_send) = self.hold(destination, events, now);
+        deliveries.record(&held, &label, &Outcome::Held);
+        let Some(event_msg) = self.combine(destination.channel, &to_send) else {
+            return if held.is_empty() {
+                Outcome::Skipped
+            } else {
+                Outcome::Held
+            };
+        };
+        let outcome = match destination.channel {
+            Channel::Discord => self.send_via_discord(&event_msg),
+            _ => self.send_via_email(&event_msg, destination.email_to.as_deref()),
+        };
+        deliveries.record(&to_send, &label, &outcome);
+        outcome
+    }
+
+    fn write_journal(&self, deliveries: &Deliveries) {
+        if let Some(journal) = &self.journal {
+            journal.write(deliveries);
+        }
+    }
+
+    /// Time until the earliest end of quiet hours of a channel with held events
+    fn time_until_next_summary(&self) -> Option<Duration> {
+        self.held
+            .borrow()
+            .iter()
+            .filter(|(_, events)| !events.is_empty())
+            .filter_map(|(destination, _)| self.quiet_hours.get(&destination.channel))
+            .filter_map(|quiet_hours| {
+                let now = Local::now();
+                let end = Schedule::daily(quiet_hours.end).next_after(&now)?;
+                (end - now).to_std().ok()
+            })
+            .min()
+    }
+
+    /// Sends a summary of the held events for each channel whose quiet hours are over
+    fn send_due_summaries(&self, now: NaiveTime) {
+        let due: Vec<(Destination, Vec<EventMessage>)> = {
+            let mut held = self.held.borrow_mut();
+            let destinations: Vec<Destination> = held
+                .keys()
+                .filter(|destination| {
+                    !self
+                        .quiet_hours
+                        .get(&destination.channel)
+                        .is_some_and(|x| x.is_active(now))
+                })
+                .cloned()
+                .collect();
+            destinations
+                .into_iter()
+                .filter_map(|destination| {
+                    held.remove(&destination)
+                        .map(|events| (destination, events))
+                })
+                .filter(|(_, events)| !events.is_empty())
+                .collect()
+        };
+        for (destination, events) in due {
+            info!(
+                "quiet hours over for {:?}, sending summary of {} events",
+                destination.channel,
+                events.len()
+            );
+            self.send_summary(&destination, &events);
+        }
+    }
+
+    /// Sends the held events as one message, except to notifiers that need each event
+    fn send_summary(&self, destination: &Destination, events: &[EventMessage]) {
+        let channel = destination.channel;
+        let included: Vec<&EventMessage> = events.iter().collect();
+        let summary = self.build_digest(channel, &included);
+        let mut deliveries = Deliveries::new(events);
+        match channel {
+            Channel::Discord => {
+                let outcome = self.send_via_discord(&summary);
+                deliveries.record(&included, &destination.label(), &outcome);
+            }
+            Channel::Email => {
+                let outcome = self.send_via_email(&summary, destination.email_to.as_deref());
+                deliveries.record(&included, &destination.label(), &outcome);
+            }
+            _ => {
+                for notifier in self.notifiers.iter().filter(|x| x.channel() == channel) {
+                    let accepted = skip_unaccepted(notifier.as_ref(), &included, &mut deliveries);
+                    if notifier.needs_each_event() {
+                        for event_message in accepted {
+                            let rendered = self.templates.render(channel, event_message);
+                            let outcome = self.send_via_notifier(notifier.as_ref(), &rendered);
+                            deliveries.record(&[event_message], notifier.name(), &outcome);
+                        }
+                    } else if !accepted.is_empty() {
+                        let outcome = self.send_via_notifier(notifier.as_ref(), &summary);
+                        deliveries.record(&accepted, notifier.name(), &outcome);
+                    }
+                }
+            }
+        }
+        self.write_journal(&deliveries);
+    }
+
+    /// Attempts to send the message via discord
+    /// Not sure if sent is guaranteed message sent but at least we couldn't detect the error
+    fn send_via_discord(&self, event_msg: &EventMessage) -> Outcome {
+        match &self.discord {
+            Some(_) if self.dry_run.is_some() => self.write_dry_run("discord", event_msg),
+            Some(discord) => match discord.send(event_msg, &event_msg.message) {
+                Ok(()) => Outcome::Sent,
+                Err(e) => {
+                    error!("failed to send message via discord: {e:?}");
+                    Outcome::failed(&e)
+                }
+            },
+            None => {
+                debug!("Discord not set. Message not sent via discord");
+                Outcome::NotConfigured
+            }
+        }
+    }
+
+    /// Attempts to send the message via email
+    /// Not sure if sent is guaranteed message sent but at least we couldn't detect the error
+    fn send_via_email(&self, event_msg: &EventMessage, to: Option<&[String]>) -> Outcome {
+        match &self.email {
+            Some(_) if self.dry_run.is_some() => {
+                let destination = Destination {
+                    channel: Channel::Email,
+                    email_to: to.map(<[String]>::to_vec),
+                };
+                self.write_dry_run(&destination.label(), event_msg)
+            }
+            Some(email) => {
+                match email.send(
+                    event_msg,
+                    &event_msg.message,
+                    &self.status_board.snapshot(),
+                    to,
+                ) {
+                    Ok(()) => Outcome::Sent,
+                    Err(e) => {
+                        error!("failed to send message via email: {e:?}");
+                        Outcome::failed(&e)
+                    }
+                }
+            }
+            None => {
+                debug!("Email not set. Message not sent via email");
+                Outcome::NotConfigured
+            }
+        }
+    }
+
+    fn write_dry_run(&self, channel: &str, event_msg: &EventMessage) -> Outcome {
+        match &self.dry_run {
+            Some(dry_run) => dry_run.write(channel, event_msg),
+            None => Outcome::NotConfigured,
+        }
+    }
+
+    /// Attempts to send the event via a notifier
+    fn send_via_notifier(&self, notifier: &dyn Notifier, event_msg: &EventMessage) -> Outcome {
+        if self.dry_run.is_some() {
+            return self.write_dry_run(notifier.name(), event_msg);
+        }
+        match notifier.notify(event_msg) {
+            Ok(()) => Outcome::Sent,
+            Err(e) => {
+                error!("failed to send message via {}: {e:?}", notifier.name());
+                Outcome::failed(&e)
+            }
+        }
+    }
+}
+
+/// The events `notifier` accepts, the rest are recorded as skipped
+fn skip_unaccepted<'a>(
+    notifier: &dyn Notifier,
+    events: &[&'a EventMessage],
+    deliveries: &mut Deliveries<'a>,
+) -> Vec<&'a EventMessage> {
+    let (accepted, skipped): (Vec<&EventMessage>, Vec<&EventMessage>) = events
+        .iter()
+        .partition(|event_message| notifier.accepts(event_message.event.kind()));
+    if !skipped.is_empty() {
+        debug!("{} skipped {} events", notifier.name(), skipped.len());
+        deliveries.record(&skipped, notifier.name(), &Outcome::Skipped);
+    }
+    accepted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread,
+    };
+
+    use rstest::rstest;
+
+    use crate::notification::exec::{Exec, ExecConfig};
+
+    use super::*;
+
+    /// Records the messages it is sent
+    struct Recorder(Arc<Mutex<Vec<EventMessage>>>);
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn channel(&self) -> Channel {
+            Channel::Webhook
+        }
+
+        fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(event_msg.clone());
+            Ok(())
+        }
+    }
+
+    fn dispatcher(batching: Option<BatchingConfig>) -> (Dispatcher, Arc<Mutex<Vec<EventMessage>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let dispatcher = Dispatcher {
+            discord: None,
+            email: None,
+            notifiers: vec![Box::new(Recorder(Arc::clone(&received)))],
+            templates: Default::default(),
+            batching,
+            quiet_hours: Default::default(),
+            held: Default::default(),
+            routes: vec![],
+            target_tags: Default::default(),
+            status_board: Default::default(),
+            dry_run: None,
+            journal: None,
+        };
+        (dispatcher, received)
+    }
+
+    #[test]
+    fn events_in_window_are_sent_as_digest() {
+        // Arrange
+        let (dispatcher, received) = dispatcher(Some(BatchingConfig {
+            window: 1.into(),
+            bypass: vec![EventKind::SystemError],
+        }));
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || dispatcher.run(rx));
+
+        // Act
+        for (name, event) in [
+            ("B", Event::ConnectionFailed(30.into())),
+            ("A", Event::ConnectionRestoredAfter(60.into(), None)),
+            ("SYSTEM_MSG", Event::SystemError("disk full".to_string())),
+            ("A", Event::ConnectionFailed(30.into())),
+        ] {
+            tx.send(EventMessage::new(name.to_string(), event)).unwrap();
+        }
+        drop(tx);
+        handle.join().unwrap();
+
+        // Assert
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2, "{received:#?}");
+        assert_eq!(received[0].event.kind(), EventKind::SystemError);
+        assert_eq!(received[1].event, Event::Digest(3));
+        let lines: Vec<&str> = received[1].text.lines().collect();
+        assert_eq!(lines.len(), 4, "{lines:?}");
+        assert!(lines[1].contains(" - A - NEW Down"), "{lines:?}");
+        assert!(lines[2].contains(" - A - Connection back UP"), "{lines:?}");
+        assert!(lines[3].contains(" - B - NEW Down"), "{lines:?}");
+    }
+
+    /// Exec notifier that appends the kind and target of each event it runs for to a file
+    fn exec_logging_to(path: &Path, event_kinds: Vec<EventKind>) -> Box<dyn Notifier> {
+        Box::new(
+            Exec::new(&ExecConfig {
+                command: "sh".to_string(),
+                args: vec![
+                    "-c".to_string(),
+                    r#"echo "$CONN_MON_EVENT_KIND $CONN_MON_TARGET" >> "$1""#.to_string(),
+                    "sh".to_string(),
+                    path.to_string_lossy().to_string(),
+                ],
+                timeout: 5.into(),
+                event_kinds: Some(event_kinds),
+            })
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn filtered_exec_gets_each_event_when_batching() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exec.log");
+        let (mut dispatcher, received) = dispatcher(Some(BatchingConfig {
+            window: 1.into(),
+            bypass: vec![],
+        }));
+        dispatcher
+            .notifiers
+            .push(exec_logging_to(&path, vec![EventKind::ConnectionFailed]));
+        let (tx, rx) = mpsc::channel();
+
+        // Act
+        for (name, event) in [
+            ("A", Event::ConnectionRestoredAfter(60.into(), None)),
+            ("Modem", Event::ConnectionFailed(30.into())),
+        ] {
+            tx.send(EventMessage::new(name.to_string(), event)).unwrap();
+        }
+        drop(tx);
+        dispatcher.run(rx);
+
+        // Assert
+        assert_eq!(received.lock().unwrap()[0].event, Event::Digest(2));
+        let actual = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(actual, "connection_failed Modem\n");
+    }
+
+    #[test]
+    fn filtered_exec_is_journaled_as_skipped() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exec.log");
+        let (mut dispatcher, _received) = dispatcher(None);
+        dispatcher.notifiers = vec![exec_logging_to(&path, vec![EventKind::ConnectionFailed])];
+        dispatcher.journal = Some(Journal::new(dir.path()));
+        let event_msg = EventMessage::new(
+            "Modem".to_string(),
+            Event::ConnectionRestoredAfter(60.into(), None),
+        );
+
+        // Act
+        dispatcher.send_to_all(&[event_msg], false, time(12, 0));
+
+        // Assert
+        assert!(!path.exists());
+        let contents = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<String>();
+        let entry: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+        assert_eq!(
+            entry["deliveries"],
+            serde_json::json!([{"channel": "exec (sh)", "outcome": "skipped"}])
+        );
+    }
+
+    #[test]
+    fn without_batching_events_are_sent_individually() {
+        let (dispatcher, received) = dispatcher(None);
+        let (tx, rx) = mpsc::channel();
+        for name in ["A", "B"] {
+            tx.send(EventMessage::new(
+                name.to_string(),
+                Event::ConnectionFailed(30.into()),
+            ))
+            .unwrap();
+        }
+        drop(tx);
+
+        dispatcher.run(rx);
+
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[rstest]
+    #[case::before_wrapping(time(21, 59), false)]
+    #[case::start(time(22, 0), true)]
+    #[case::after_midnight(time(3, 0), true)]
+    #[case::end(time(7, 0), false)]
+    fn quiet_hours_wrap_past_midnight(#[case] now: NaiveTime, #[case] expected: bool) {
+        let quiet_hours = QuietHoursConfig {
+            start: time(22, 0),
+            end: time(7, 0),
+            min_severity: Severity::High,
+        };
+
+        let actual = quiet_hours.is_active(now);
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn low_severity_held_until_quiet_hours_end() {
+        // Arrange
+        let (mut dispatcher, received) = dispatcher(None);
+        dispatcher.quiet_hours.insert(
+            Channel::Webhook,
+            QuietHoursConfig {
+                start: time(22, 0),
+                end: time(7, 0),
+                min_severity: Severity::High,
+            },
+        );
+        let events = [
+            EventMessage::new("A".to_string(), Event::ConnectionStillDown(3600.into())),
+            EventMessage::new("B".to_string(), Event::ConnectionFailed(30.into())),
+            EventMessage::new(
+                "A".to_string(),
+                Event::ConnectionRestoredAfter(4000.into(), None),
+            ),
+        ];
+
+        // Act
+        for event_msg in events.iter() {
+            dispatcher.send_to_all(std::slice::from_ref(event_msg), false, time(2, 0));
+        }
+        dispatcher.send_due_summaries(time(6, 0));
+        let during_quiet_hours = received.lock().unwrap().len();
+        dispatcher.send_due_summaries(time(7, 0));
+
+        // Assert
+        let received = received.lock().unwrap();
+        assert_eq!(during_quiet_hours, 1);
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].event.kind(), EventKind::ConnectionFailed);
+        assert_eq!(received[1].event, Event::Digest(2));
+        assert!(dispatcher.held.borrow().is_empty());
+    }
+
+    #[test]
+    fn filtered_exec_gets_each_held_event_after_quiet_hours() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exec.log");
+        let (mut dispatcher, _received) = dispatcher(None);
+        dispatcher
+            .notifiers
+            .push(exec_logging_to(&path, vec![EventKind::ConnectionStillDown]));
+        dispatcher.quiet_hours.insert(
+            Channel::Exec,
+            QuietHoursConfig {
+                start: time(22, 0),
+                end: time(7, 0),
+                min_severity: Severity::High,
+            },
+        );
+        let batch = vec![
+            EventMessage::new("Modem".to_string(), Event::ConnectionStillDown(3600.into())),
+            EventMessage::new(
+                "A".to_string(),
+                Event::ConnectionRestoredAfter(4000.into(), None),
+            ),
+        ];
+
+        // Act
+        dispatcher.send_to_all(&batch, false, time(2, 0));
+        let during_quiet_hours = path.exists();
+        dispatcher.send_due_summaries(time(7, 0));
+
+        // Assert
+        assert!(!during_quiet_hours);
+        let actual = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(actual, "connection_still_down Modem\n");
+    }
+
+    #[test]
+    fn held_or_empty_email_is_not_reported_as_sent() {
+        // Arrange
+        let (mut dispatcher, _received) = dispatcher(None);
+        let email_config = serde_json::from_str(
+            r#"{
+                "from_name": "conn_mon",
+                "from_email": "conn_mon@example.com",
+                "to": ["admin@example.com"],
+                "smtp_host": "localhost",
+                "tls": "none",
+                "auth": "none"
+            }"#,
+        )
+        .unwrap();
+        dispatcher.email = Some(Email::new(&email_config).unwrap());
+        dispatcher.quiet_hours.insert(
+            Channel::Email,
+            QuietHoursConfig {
+                start: time(22, 0),
+                end: time(7, 0),
+                min_severity: Severity::High,
+            },
+        );
+        let events = [EventMessage::new(
+            "A".to_string(),
+            Event::ConnectionStillDown(3600.into()),
+        )];
+        let mut deliveries = Deliveries::new(&events);
+        let destination = Destination::new(Channel::Email);
+
+        // Act
+        let held = dispatcher.send_to_destination(
+            &destination,
+            &[&events[0]],
+            time(2, 0),
+            &mut deliveries,
+        );
+        let empty = dispatcher.send_to_destination(&destination, &[], time(2, 0), &mut deliveries);
+
+        // Assert
+        assert_eq!(held, Outcome::Held);
+        assert_eq!(empty, Outcome::Skipped);
+        assert!(held.is_handled() && empty.is_handled());
+    }
+
+    #[test]
+    fn routed_events_skip_other_channels() {
+        // Arrange
+        let (mut dispatcher, received) = dispatcher(None);
+        dispatcher.routes = vec![RouteConfig {
+            targets: vec![],
+            tags: vec!["lab-network".to_string()],
+            event_kinds: None,
+            channels: vec![Channel::Matrix],
+            email_to: None,
+        }];
+        dispatcher.target_tags =
+            HashMap::from([("Lab".to_string(), vec!["lab-network".to_string()])]);
+        let batch = vec![
+            EventMessage::new("Lab".to_string(), Event::ConnectionFailed(30.into())),
+            EventMessage::new("Uplink".to_string(), Event::ConnectionFailed(30.into())),
+        ];
+
+        // Act
+        dispatcher.send_to_all(&batch, false, time(12, 0));
+
+        // Assert
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].host_disp_name, "Uplink");
+    }
+
+    /// Output for dry runs that can be inspected after
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dry_run_writes_instead_of_sending() {
+        // Arrange
+        let (mut dispatcher, received) = dispatcher(None);
+        let output = SharedOutput::default();
+        dispatcher.dry_run = Some(DryRun(RefCell::new(Box::new(output.clone()))));
+        let event_msg = EventMessage::new("Uplink".to_string(), Event::ConnectionFailed(30.into()));
+
+        // Act
+        dispatcher.send_to_all(&[event_msg], false, time(12, 0));
+
+        // Assert
+        assert!(received.lock().unwrap().is_empty());
+        let written = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            written.starts_with("[dry run] recorder: ") && written.contains(" - Uplink - NEW Down"),
+            "{written}"
+        );
+    }
+
+    /// Always fails to send
+    struct Failing;
+
+    impl Notifier for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn channel(&self) -> Channel {
+            Channel::Slack
+        }
+
+        fn notify(&self, _event_msg: &EventMessage) -> anyhow::Result<()> {
+            anyhow::bail!("service unavailable")
+        }
+    }
+
+    #[test]
+    fn journal_records_outcome_per_channel() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let (mut dispatcher, _received) = dispatcher(None);
+        dispatcher.notifiers.push(Box::new(Failing));
+        dispatcher.journal = Some(Journal::new(dir.path()));
+        dispatcher.quiet_hours.insert(
+            Channel::Webhook,
+            QuietHoursConfig {
+                start: time(22, 0),
+                end: time(7, 0),
+                min_severity: Severity::High,
+            },
+        );
+        let batch = vec![
+            EventMessage::new("A".to_string(), Event::ConnectionStillDown(3600.into())),
+            EventMessage::new("B".to_string(), Event::ConnectionFailed(30.into())),
+        ];
+
+        // Act
+        dispatcher.send_to_all(&batch, false, time(2, 0));
+        dispatcher.send_due_summaries(time(7, 0));
+
+        // Assert
+        let contents = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect::<String>();
+        let entries: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<(&str, &str, serde_json::Value)> = entries
+            .iter()
+            .map(|x| {
+                (
+                    x["target"].as_str().unwrap(),
+                    x["event"].as_str().unwrap(),
+                    x["deliveries"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "A",
+                    "connection_still_down",
+                    serde_json::json!([
+                        {"channel": "recorder", "outcome": "held"},
+                        {"channel": "failing", "outcome": "failed", "error": "service unavailable"},
+                    ])
+                ),
+                (
+                    "B",
+                    "connection_failed",
+                    serde_json::json!([
+                        {"channel": "recorder", "outcome": "sent"},
+                        {"channel": "failing", "outcome": "failed", "error": "service unavailable"},
+                    ])
+                ),
+                (
+                    "A",
+                    "connection_still_down",
+                    serde_json::json!([{"channel": "recorder", "outcome": "sent"}])
+                ),
+            ]
+        );
+    }
+}
@@ -1,84 +1,505 @@
-use std::fs;
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::Context;
-use lettre::message::Mailbox;
-use lettre::transport::smtp::authentication::Credentials;
+use anyhow::{bail, Context};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
 use lettre::{Message, SmtpTransport, Transport};
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+use crate::{
+    event_recorder::{EventMessage, TargetStatus},
+    secret::Secret,
+    state_management::{Status, VERSION},
+};
+
+use super::{escape_html, event_color, template::Template, OutageThreads};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
-struct EmailConfig {
-    from_name: String,
-    pass: String,
-    #[serde(default = "EmailConfig::default_from_email")]
-    from_email: String,
-    #[serde(default = "EmailConfig::default_to_email")]
-    to_email: String,
+pub struct EmailConfig {
+    /// Name displayed as the sender
+    pub from_name: String,
+
+    /// Address emails are sent from
+    pub from_email: String,
+
+    /// Recipients of the emails
+    pub to: Vec<String>,
+
+    /// Recipients to copy on the emails
+    #[serde(default)]
+    pub cc: Vec<String>,
+
+    /// Host name of the SMTP server
+    pub smtp_host: String,
+
+    /// Port of the SMTP server, if not set the standard port for the TLS mode is used
+    pub smtp_port: Option<u16>,
+
+    /// How the connection to the SMTP server is secured
+    #[serde(default)]
+    pub tls: TlsMode,
+
+    /// Mechanism used to authenticate with the SMTP server
+    #[serde(default)]
+    pub auth: AuthMechanism,
+
+    /// User to authenticate as, defaults to `from_email`
+    pub username: Option<String>,
+
+    /// Password (or token for xoauth2) to authenticate with, not needed if auth is none
+    #[serde(alias = "pass")]
+    pub password: Option<Secret>,
+
+    /// Subject of the emails, supports the same placeholders as the templates
     #[serde(default = "EmailConfig::default_subject")]
-    subject: String,
+    pub subject: Template,
 }
 
 impl EmailConfig {
-    fn default_from_email() -> String {
-        "wykies.notices@gmail.com".to_string()
-    }
-    fn default_to_email() -> String {
-        "it@wykies.com".to_string()
-    }
-    fn default_subject() -> String {
-        "Notification from connection monitor".to_string()
+    /// File the email settings were read from before they were part of the config
+    pub(crate) const LEGACY_FILENAME: &'static str = "e.data";
+
+    fn default_subject() -> Template {
+        Template::try_from("[conn_mon] {target} - {event_kind}".to_string())
+            .expect("default subject only uses known placeholders")
     }
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    /// TLS from the start of the connection (Default port 465)
+    #[default]
+    Implicit,
+    /// Plain connection upgraded using STARTTLS, fails if not supported (Default port 587)
+    Starttls,
+    /// Unencrypted, only suitable for a local relay (Default port 25)
+    None,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMechanism {
+    #[default]
+    Plain,
+    Login,
+    Xoauth2,
+    None,
+}
+
 pub struct Email {
     from_mailbox: Mailbox,
-    to_mailbox: Mailbox,
-    subject: String,
+    to_mailboxes: Vec<Mailbox>,
+    cc_mailboxes: Vec<Mailbox>,
+    subject: Template,
     transport: SmtpTransport,
+    /// Used to make message IDs unique across restarts
+    start_millis: u128,
+    message_counter: AtomicU64,
+    outage_threads: OutageThreads,
 }
 impl Email {
-    pub fn new() -> anyhow::Result<Self> {
-        let filename = "e.data";
-        let file_contents = fs::read_to_string(filename)
-            .with_context(|| format!("failed to read email settings from {filename:?}"))?;
-        let email_config: EmailConfig = serde_json::from_str(&file_contents)
-            .with_context(|| format!("failed to parse contents of {filename:?} as email config"))?;
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn new(email_config: &EmailConfig) -> anyhow::Result<Self> {
         let from_mailbox = Mailbox {
-            name: Some(email_config.from_name),
+            name: Some(email_config.from_name.clone()),
             email: email_config
                 .from_email
                 .parse()
                 .context("failed to parse from email address")?,
         };
-        let to_mailbox: Mailbox = email_config
-            .to_email
-            .parse()
-            .context("failed to parse to email address")?;
-        let transport = SmtpTransport::relay("smtp.gmail.com")
-            .context("failed to build SmtpTransport")?
-            .credentials(Credentials::new(email_config.from_email, email_config.pass))
-            .build();
-        let subject = email_config.subject;
+        if email_config.to.is_empty() {
+            bail!("no recipients set for email");
+        }
+        let to_mailboxes = parse_mailboxes(&email_config.to).context("invalid to address")?;
+        let cc_mailboxes = parse_mailboxes(&email_config.cc).context("invalid cc address")?;
+
+        let host = &email_config.smtp_host;
+        let mut builder = match email_config.tls {
+            TlsMode::Implicit => SmtpTransport::relay(host),
+            TlsMode::Starttls => SmtpTransport::starttls_relay(host),
+            TlsMode::None => Ok(SmtpTransport::builder_dangerous(host)),
+        }
+        .context("failed to build SmtpTransport")?
+        .timeout(Some(Self::TIMEOUT));
+        if let Some(port) = email_config.smtp_port {
+            builder = builder.port(port);
+        }
+        let mechanism = match email_config.auth {
+            AuthMechanism::Plain => Some(Mechanism::Plain),
+            AuthMechanism::Login => Some(Mechanism::Login),
+            AuthMechanism::Xoauth2 => Some(Mechanism::Xoauth2),
+            AuthMechanism::None => None,
+        };
+        if let Some(mechanism) = mechanism {
+            let Some(password) = email_config
+                .password
+                .as_ref()
+                .map(|x| x.expose().to_string())
+            else {
+                bail!(
+                    "email password is required for {:?} authentication",
+                    email_config.auth
+                );
+            };
+            let username = email_config
+                .username
+                .clone()
+                .unwrap_or_else(|| email_config.from_email.clone());
+            builder = builder
+                .credentials(Credentials::new(username, password))
+                .authentication(vec![mechanism]);
+        }
+
+        let start_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system time is before unix epoch")?
+            .as_millis();
+
         Ok(Self {
             from_mailbox,
-            to_mailbox,
-            subject,
-            transport,
+            to_mailboxes,
+            cc_mailboxes,
+            subject: email_config.subject.clone(),
+            transport: builder.build(),
+            start_millis,
+            message_counter: Default::default(),
+            outage_threads: Default::default(),
         })
     }
 
-    pub fn send(&self, msg: &str) -> anyhow::Result<()> {
+    fn build_subject(&self, event_msg: &EventMessage) -> String {
+        self.subject.render(event_msg, &event_msg.text)
+    }
+
+    fn next_message_id(&self) -> String {
+        format!(
+            "<conn_mon.{}.{}@{}>",
+            self.start_millis,
+            self.message_counter.fetch_add(1, Ordering::Relaxed),
+            self.from_mailbox.email.domain()
+        )
+    }
+
+    fn build_html(
+        event_msg: &EventMessage,
+        statuses: &[(String, TargetStatus)],
+    ) -> anyhow::Result<String> {
+        let EventMessage {
+            host_disp_name: name,
+            timestamp,
+            event,
+            text,
+            ..
+        } = event_msg;
+        let mut result = String::new();
+        write!(
+            result,
+            "<html><body>\
+            <p style=\"border-left: 4px solid #{:06X}; padding-left: 8px\"><b>{}</b><br/>{}<br/><i>{}</i></p>",
+            event_color(event.kind()),
+            escape_html(name),
+            escape_html(text),
+            escape_html(&timestamp.to_string())
+        )?;
+        if !statuses.is_empty() {
+            result.push_str(
+                "<table border=\"1\" cellpadding=\"4\" style=\"border-collapse: collapse\">\
+                <tr><th>Target</th><th>Status</th><th>Since</th><th>Last RTT</th></tr>",
+            );
+            for (target, status) in statuses {
+                let color = match status.status {
+                    Status::Up => "#43A047",
+                    Status::Down => "#E53935",
+                    Status::SystemError => "#8E24AA",
+                    Status::Unknown => "#757575",
+                };
+                write!(
+                    result,
+                    "<tr><td>{}</td><td style=\"color: {color}\">{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(target),
+                    status.status,
+                    escape_html(&status.since.to_string()),
+                    status
+                        .last_rtt
+                        .map(|x| x.to_string())
+                        .unwrap_or_else(|| "-".to_string())
+                )?;
+            }
+            result.push_str("</table>");
+        }
+        write!(
+            result,
+            "<p style=\"color: #757575\">conn_mon {VERSION}</p></body></html>"
+        )?;
+        Ok(result)
+    }
+
+    fn build_plain(msg: &str, statuses: &[(String, TargetStatus)]) -> String {
+        let mut result = format!("{msg}\n");
+        if !statuses.is_empty() {
+            result.push_str("\nStatus of all targets:\n");
+            for (target, status) in statuses {
+                result.push_str(&format!(
+                    "- {target}: {} since {}\n",
+                    status.status, status.since
+                ));
+            }
+        }
+        result
+    }
+
+    /// Sends the email, replies to the email that started the outage if there is one.
+    /// If `to` is set it is used instead of the configured recipients.
+    pub fn send(
+        &self,
+        event_msg: &EventMessage,
+        msg: &str,
+        statuses: &[(String, TargetStatus)],
+        to: Option<&[String]>,
+    ) -> anyhow::Result<()> {
         warn!("EMAIL MESSAGE: {msg}");
-        let email = Message::builder()
-            .from(self.from_mailbox.clone())
-            .to(self.to_mailbox.clone())
-            .subject(self.subject.clone())
-            .body(msg.to_string())?;
-        self.transport
-            .send(&email)
-            .context("failed to send email")?;
-        Ok(())
+        let to_mailboxes = match to {
+            Some(to) => parse_mailboxes(to).context("invalid routed email recipient")?,
+            None => self.to_mailboxes.clone(),
+        };
+        let body = MultiPart::alternative_plain_html(
+            Self::build_plain(msg, statuses),
+            Self::build_html(event_msg, statuses).context("failed to build html body")?,
+        );
+        self.outage_threads.send(event_msg, |thread_root| {
+            let message_id = self.next_message_id();
+            let mut builder = Message::builder()
+                .from(self.from_mailbox.clone())
+                .subject(self.build_subject(event_msg))
+                .message_id(Some(message_id.clone()));
+            for mailbox in to_mailboxes.iter() {
+                builder = builder.to(mailbox.clone());
+            }
+            for mailbox in self.cc_mailboxes.iter() {
+                builder = builder.cc(mailbox.clone());
+            }
+            if let Some(root) = thread_root {
+                builder = builder
+                    .in_reply_to(root.to_string())
+                    .references(root.to_string());
+            }
+            let email = builder.multipart(body)?;
+            self.transport
+                .send(&email)
+                .context("failed to send email")?;
+            Ok(message_id)
+        })
+    }
+}
+
+fn parse_mailboxes(addresses: &[String]) -> anyhow::Result<Vec<Mailbox>> {
+    addresses
+        .iter()
+        .map(|address| {
+            address
+                .parse()
+                .with_context(|| format!("failed to parse email address: {address:?}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc::{self, Receiver},
+        thread,
+    };
+
+    use crate::state_management::Event;
+
+    use super::*;
+
+    /// Recipients and data of a message received by the [`start_smtp_sink`]
+    struct ReceivedMail {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    /// Starts a minimal SMTP server that accepts any message and returns its port
+    fn start_smtp_sink() -> (u16, Receiver<ReceivedMail>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                let mut recipients = vec![];
+                write!(stream, "220 localhost ESMTP test\r\n").unwrap();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 {
+                        break;
+                    }
+                    let command = line.trim_end().to_ascii_uppercase();
+                    if command.starts_with("EHLO") || command.starts_with("HELO") {
+                        write!(stream, "250 localhost\r\n").unwrap();
+                    } else if command.starts_with("RCPT TO:") {
+                        recipients.push(line.trim_end()[8..].to_string());
+                        write!(stream, "250 OK\r\n").unwrap();
+                    } else if command == "DATA" {
+                        write!(stream, "354 Go ahead\r\n").unwrap();
+                        let mut data = String::new();
+                        loop {
+                            let mut line = String::new();
+                            reader.read_line(&mut line).unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            data.push_str(&line);
+                        }
+                        write!(stream, "250 Queued\r\n").unwrap();
+                        let _ = tx.send(ReceivedMail {
+                            recipients: std::mem::take(&mut recipients),
+                            data,
+                        });
+                    } else if command == "QUIT" {
+                        write!(stream, "221 Bye\r\n").unwrap();
+                        break;
+                    } else {
+                        write!(stream, "250 OK\r\n").unwrap();
+                    }
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    fn local_config(port: u16) -> EmailConfig {
+        EmailConfig {
+            from_name: "Conn Mon".to_string(),
+            from_email: "monitor@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            cc: vec!["c@example.com".to_string()],
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: Some(port),
+            tls: TlsMode::None,
+            auth: AuthMechanism::None,
+            username: None,
+            password: None,
+            subject: Template::try_from("[{event_kind}] {target}".to_string()).unwrap(),
+        }
+    }
+
+    /// Value of the header with the given name (header lines are short enough not to be folded)
+    fn header<'a>(data: &'a str, name: &str) -> Option<&'a str> {
+        data.lines()
+            .find_map(|line| line.strip_prefix(&format!("{name}: ")))
+    }
+
+    #[test]
+    fn unknown_subject_placeholder_is_error() {
+        let actual = serde_json::from_str::<EmailConfig>(
+            r#"{"from_name": "Conn Mon", "from_email": "monitor@example.com", "to": [],
+                "smtp_host": "127.0.0.1", "subject": "{target} is {status}"}"#,
+        );
+
+        let err = actual.unwrap_err().to_string();
+        assert!(err.contains("unknown placeholder {status}"), "{err}");
+    }
+
+    #[test]
+    fn delivers_to_all_recipients() {
+        // Arrange
+        let (port, rx) = start_smtp_sink();
+        let config = local_config(port);
+        let email = Email::new(&config).unwrap();
+        let event_msg =
+            EventMessage::new("Google DNS".to_string(), Event::ConnectionFailed(30.into()));
+
+        // Act
+        email.send(&event_msg, "body text", &[], None).unwrap();
+
+        // Assert
+        let mail = rx.recv().unwrap();
+        assert_eq!(
+            mail.recipients,
+            vec!["<a@example.com>", "<b@example.com>", "<c@example.com>"]
+        );
+        assert!(mail
+            .data
+            .contains("Subject: [connection_failed] Google DNS"));
+        assert!(mail.data.contains("Cc: c@example.com"));
+        assert!(mail.data.contains("body text"));
+    }
+
+    #[test]
+    fn outage_emails_are_threaded() {
+        // Arrange
+        let (port, rx) = start_smtp_sink();
+        let email = Email::new(&local_config(port)).unwrap();
+        let name = "Google DNS".to_string();
+
+        // Act
+        for event in [
+            Event::ConnectionFailed(30.into()),
+            Event::ConnectionStillDown(3630.into()),
+            Event::ConnectionRestoredAfter(4000.into(), None),
+        ] {
+            email
+                .send(
+                    &EventMessage::new(name.clone(), event),
+                    "body text",
+                    &[],
+                    None,
+                )
+                .unwrap();
+        }
+
+        // Assert
+        let first = rx.recv().unwrap().data;
+        let root = header(&first, "Message-ID").unwrap();
+        assert!(header(&first, "In-Reply-To").is_none());
+        for _ in 0..2 {
+            let reply = rx.recv().unwrap().data;
+            assert_eq!(header(&reply, "In-Reply-To"), Some(root));
+            assert_eq!(header(&reply, "References"), Some(root));
+        }
+    }
+
+    #[test]
+    fn html_has_status_table() {
+        let event_msg = EventMessage::new("<Lab>".to_string(), Event::ConnectionFailed(30.into()));
+        let statuses = vec![(
+            "<Lab>".to_string(),
+            TargetStatus {
+                status: Status::Down,
+                since: Default::default(),
+                last_rtt: Some(5.into()),
+            },
+        )];
+
+        let actual = Email::build_html(&event_msg, &statuses).unwrap();
+
+        assert!(actual.contains("<td>&lt;Lab&gt;</td><td style=\"color: #E53935\">Down</td>"));
+        assert!(actual.contains("<td>5 ms</td>"));
+    }
+
+    #[test]
+    fn password_required_for_auth() {
+        let config: EmailConfig = serde_json::from_str(
+            r#"{"from_name": "a", "from_email": "a@example.com", "to": ["b@example.com"], "smtp_host": "localhost"}"#,
+        )
+        .unwrap();
+
+        let actual = Email::new(&config);
+
+        assert!(actual.is_err());
     }
 }
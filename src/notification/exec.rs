@@ -0,0 +1,247 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_recorder::EventMessage,
+    state_management::{EventKind, VERSION},
+    Seconds,
+};
+
+use super::{Channel, EventPayload, Notifier};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecConfig {
+    /// Program to run for each event
+    pub command: String,
+
+    /// Arguments to pass to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Maximum time to wait for the program to finish before it is killed
+    #[serde(default = "ExecConfig::default_timeout")]
+    pub timeout: Seconds,
+
+    /// If set only events of these kinds run the program
+    pub event_kinds: Option<Vec<EventKind>>,
+}
+
+impl ExecConfig {
+    fn default_timeout() -> Seconds {
+        30.into()
+    }
+}
+
+pub struct Exec {
+    name: String,
+    config: ExecConfig,
+}
+
+impl Exec {
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn new(config: &ExecConfig) -> anyhow::Result<Self> {
+        if config.command.trim().is_empty() {
+            bail!("exec command is empty");
+        }
+        Ok(Self {
+            name: format!("exec ({})", config.command),
+            config: config.clone(),
+        })
+    }
+
+    fn spawn(&self, event_msg: &EventMessage) -> anyhow::Result<Child> {
+        let event = &event_msg.event;
+        let mut cmd = Command::new(&self.config.command);
+        cmd.args(&self.config.args)
+            .env("CONN_MON_TARGET", &event_msg.host_disp_name)
+            .env("CONN_MON_EVENT_KIND", event.kind().as_str())
+            .env("CONN_MON_MESSAGE", &event_msg.text)
+            .env("CONN_MON_TIMESTAMP", event_msg.timestamp.to_string())
+            .env("CONN_MON_VERSION", VERSION)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(duration) = event.duration() {
+            cmd.env("CONN_MON_DURATION_SECS", duration.as_u64().to_string());
+        }
+        if let Some(err_msg) = event.error_msg() {
+            cmd.env("CONN_MON_ERROR", err_msg);
+        }
+        cmd.spawn()
+            .with_context(|| format!("failed to start {:?}", self.config.command))
+    }
+
+    /// Reads the stream to the end on a separate thread so the child does not block on a full pipe
+    fn read_in_background<R: Read + Send + 'static>(stream: Option<R>) -> JoinHandle<String> {
+        thread::spawn(move || {
+            let mut result = String::new();
+            if let Some(mut stream) = stream {
+                let _ = stream.read_to_string(&mut result);
+            }
+            result
+        })
+    }
+
+    /// Writes to stdin on a separate thread so a program that doesn't read it cannot block past
+    /// the timeout. The write fails once the child exits or is killed, which ends the thread
+    fn write_in_background(&self, stdin: Option<ChildStdin>, payload: Vec<u8>) {
+        let Some(mut stdin) = stdin else {
+            return;
+        };
+        let name = self.name.clone();
+        thread::spawn(move || {
+            // Not an error if the program doesn't read stdin
+            if let Err(e) = stdin.write_all(&payload) {
+                debug!("failed to write event to stdin of {name}: {e}");
+            }
+        });
+    }
+}
+
+impl Notifier for Exec {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Exec
+    }
+
+    fn needs_each_event(&self) -> bool {
+        true
+    }
+
+    fn accepts(&self, kind: EventKind) -> bool {
+        match &self.config.event_kinds {
+            Some(kinds) => kinds.contains(&kind),
+            None => true,
+        }
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("EXEC MESSAGE: {}", event_msg.message);
+        let mut child = self.spawn(event_msg)?;
+        let stdout = Self::read_in_background(child.stdout.take());
+        let stderr = Self::read_in_background(child.stderr.take());
+
+        let payload = serde_json::to_vec(&EventPayload::from(event_msg))
+            .context("failed to serialize event for stdin")?;
+        self.write_in_background(child.stdin.take(), payload);
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().context("failed to wait on child")? {
+                break status;
+            }
+            if start.elapsed() >= Duration::from(self.config.timeout) {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!("{} timed out after {}", self.name, self.config.timeout);
+            }
+            thread::sleep(Self::POLL_INTERVAL);
+        };
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        debug!("{} finished with {status}. stdout: {stdout:?}", self.name);
+        if !status.success() {
+            bail!("{} failed with {status}. stderr: {stderr:?}", self.name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_management::Event;
+
+    use super::*;
+
+    fn shell(script: &str, timeout: u64, event_kinds: Option<Vec<EventKind>>) -> Exec {
+        Exec::new(&ExecConfig {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            timeout: timeout.into(),
+            event_kinds,
+        })
+        .unwrap()
+    }
+
+    fn failed_event() -> EventMessage {
+        EventMessage::new("Google DNS".to_string(), Event::ConnectionFailed(30.into()))
+    }
+
+    #[test]
+    fn passes_event_via_env_and_stdin() {
+        let exec = shell(
+            r#"test "$CONN_MON_EVENT_KIND" = connection_failed \
+                && test "$CONN_MON_DURATION_SECS" = 30 \
+                && grep -q '"target":"Google DNS"'"#,
+            5,
+            None,
+        );
+
+        let actual = exec.notify(&failed_event());
+
+        assert!(actual.is_ok(), "{actual:?}");
+    }
+
+    #[test]
+    fn non_zero_exit_is_error() {
+        let exec = shell("echo broken >&2; exit 3", 5, None);
+
+        let actual = exec.notify(&failed_event());
+
+        let err = format!("{:?}", actual.unwrap_err());
+        assert!(err.contains("broken"), "{err}");
+    }
+
+    #[test]
+    fn killed_after_timeout() {
+        let exec = shell("sleep 10", 1, None);
+
+        let actual = exec.notify(&failed_event());
+
+        let err = format!("{:?}", actual.unwrap_err());
+        assert!(err.contains("timed out"), "{err}");
+    }
+
+    #[test]
+    fn unread_stdin_does_not_block_timeout() {
+        // Arrange
+        let exec = shell("sleep 10", 1, None);
+        // Larger than the pipe buffer so writing it blocks until the program exits
+        let event_msg = EventMessage::new("x".repeat(100_000), Event::ConnectionFailed(30.into()));
+        let start = Instant::now();
+
+        // Act
+        let actual = exec.notify(&event_msg);
+
+        // Assert
+        let err = format!("{:?}", actual.unwrap_err());
+        assert!(err.contains("timed out"), "{err}");
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn only_accepts_filtered_kinds() {
+        let exec = shell("exit 1", 5, Some(vec![EventKind::ConnectionRestoredAfter]));
+
+        assert!(exec.accepts(EventKind::ConnectionRestoredAfter));
+        assert!(!exec.accepts(EventKind::ConnectionFailed));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fs::{create_dir_all, File},
+    io::Write,
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use chrono::Local;
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    event_recorder::{EventMessage, Timestamp},
+    secret,
+    state_management::EventKind,
+};
+
+/// Result of trying to deliver an event via a channel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "error", rename_all = "snake_case")]
+pub(crate) enum Outcome {
+    Sent,
+    /// Written to the dry run output instead of being sent
+    DryRun,
+    /// Held until the end of quiet hours, a later entry records the delivery
+    Held,
+    Failed(String),
+    /// The channel does not send events of this kind
+    Skipped,
+    /// The channel is not set up
+    NotConfigured,
+}
+
+impl Outcome {
+    pub(crate) fn failed(e: &anyhow::Error) -> Self {
+        Self::Failed(secret::redact(&format!("{e:#}")))
+    }
+
+    /// If nothing more needs to be done for the events, they were delivered (or would have been
+    /// in a dry run), held for later or there was nothing to send
+    pub(crate) fn is_handled(&self) -> bool {
+        matches!(
+            self,
+            Outcome::Sent | Outcome::DryRun | Outcome::Held | Outcome::Skipped
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Delivery {
+    channel: String,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+struct JournalEntry<'a> {
+    /// When the event happened
+    timestamp: &'a Timestamp,
+    /// When delivery was attempted (differs from `timestamp` for batched or held events)
+    recorded: Timestamp,
+    target: &'a str,
+    event: EventKind,
+    text: &'a str,
+    deliveries: &'a [Delivery],
+}
+
+/// Collects the delivery outcomes for a group of events dispatched together
+pub(crate) struct Deliveries<'a> {
+    events: &'a [EventMessage],
+    outcomes: Vec<Vec<Delivery>>,
+}
+
+impl<'a> Deliveries<'a> {
+    pub(crate) fn new(events: &'a [EventMessage]) -> Self {
+        Self {
+            events,
+            outcomes: events.iter().map(|_| vec![]).collect(),
+        }
+    }
+
+    /// Records the outcome for each of `included` (which must be from the events this was created with)
+    pub(crate) fn record(&mut self, included: &[&EventMessage], channel: &str, outcome: &Outcome) {
+        for event_message in included {
+            let Some(index) = self
+                .events
+                .iter()
+                .position(|x| std::ptr::eq(x, *event_message))
+            else {
+                error!(
+                    "delivery recorded for an event that is not being tracked: {event_message:?}"
+                );
+                continue;
+            };
+            self.outcomes[index].push(Delivery {
+                channel: channel.to_string(),
+                outcome: outcome.clone(),
+            });
+        }
+    }
+}
+
+/// Append only record of every event and how it was delivered, one JSON object per line
+#[derive(Debug)]
+pub(crate) struct Journal {
+    folder: PathBuf,
+}
+
+impl Journal {
+    pub(crate) fn new(folder: impl Into<PathBuf>) -> Self {
+        Self {
+            folder: folder.into(),
+        }
+    }
+
+    /// Path of the file for entries recorded today
+    fn path(&self) -> PathBuf {
+        self.folder
+            .join(format!("{} journal.jsonl", Local::now().format("%F")))
+    }
+
+    /// Appends an entry for each event, errors are only logged as they should not stop delivery
+    pub(crate) fn write(&self, deliveries: &Deliveries) {
+        if let Err(e) = self.try_write(deliveries) {
+            error!("failed to write to event journal: {e:?}");
+        }
+    }
+
+    fn try_write(&self, deliveries: &Deliveries) -> anyhow::Result<()> {
+        create_dir_all(&self.folder)
+            .with_context(|| format!("failed to create journal folder {:?}", self.folder))?;
+        let path = self.path();
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {path:?}"))?;
+        let recorded = Timestamp::new();
+        for (event_message, outcomes) in deliveries.events.iter().zip(deliveries.outcomes.iter()) {
+            let entry = JournalEntry {
+                timestamp: &event_message.timestamp,
+                recorded: recorded.clone(),
+                target: &event_message.host_disp_name,
+                event: event_message.event.kind(),
+                text: &event_message.text,
+                deliveries: outcomes,
+            };
+            let line = serde_json::to_string(&entry).context("failed to serialize entry")?;
+            writeln!(file, "{line}").with_context(|| format!("failed to write to {path:?}"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use log::warn;
+use reqwest::{blocking::Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{event_recorder::EventMessage, secret::Secret};
+
+use super::{escape_html, Channel, Notifier, OutageThreads};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig {
+    /// Base url of the homeserver (eg. https://matrix.org)
+    pub homeserver_url: String,
+
+    /// ID of the room to send to (eg. !abcdefg:matrix.org), the user must already be joined
+    pub room_id: String,
+
+    /// Access token of the user messages are sent as
+    pub access_token: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendResponse {
+    event_id: String,
+}
+
+pub struct Matrix {
+    client: Client,
+    send_url: Url,
+    access_token: String,
+    /// Used to make transaction IDs unique across restarts
+    start_millis: u128,
+    txn_counter: AtomicU64,
+    outage_threads: OutageThreads,
+}
+
+impl Matrix {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(config: &MatrixConfig) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        let mut send_url = Url::parse(&config.homeserver_url)
+            .with_context(|| format!("invalid homeserver url: {:?}", config.homeserver_url))?;
+        send_url
+            .path_segments_mut()
+            .map_err(|()| anyhow!("homeserver url cannot be a base url"))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &config.room_id,
+                "send",
+                "m.room.message",
+            ]);
+        let start_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system time is before unix epoch")?
+            .as_millis();
+        Ok(Self {
+            client,
+            send_url,
+            access_token: config.access_token.expose().to_string(),
+            start_millis,
+            txn_counter: Default::default(),
+            outage_threads: Default::default(),
+        })
+    }
+
+    fn next_txn_url(&self) -> Url {
+        let txn_id = format!(
+            "conn_mon.{}.{}",
+            self.start_millis,
+            self.txn_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut result = self.send_url.clone();
+        result
+            .path_segments_mut()
+            .expect("already checked when url was built")
+            .push(&txn_id);
+        result
+    }
+
+    /// Sends the message and returns the event ID matrix assigned to it
+    fn send(&self, event_msg: &EventMessage, thread_root: Option<&str>) -> anyhow::Result<String> {
+        let EventMessage {
+            host_disp_name: name,
+            timestamp,
+            text,
+            message,
+            ..
+        } = event_msg;
+        let mut content = json!({
+            "msgtype": "m.text",
+            "body": message.clone(),
+            "format": "org.matrix.custom.html",
+            "formatted_body": format!(
+                "<b>{}</b><br/>{}<br/><i>{}</i>",
+                escape_html(name),
+                escape_html(text),
+                escape_html(&timestamp.to_string())
+            ),
+        });
+        if let Some(root) = thread_root {
+            content["m.relates_to"] = json!({
+                "rel_type": "m.thread",
+                "event_id": root,
+            });
+        }
+        let response: SendResponse = self
+            .client
+            .put(self.next_txn_url())
+            .bearer_auth(&self.access_token)
+            .json(&content)
+            .send()
+            .context("failed to send request to matrix")?
+            .error_for_status()
+            .context("matrix responded with an error")?
+            .json()
+            .context("failed to parse matrix response")?;
+        Ok(response.event_id)
+    }
+}
+
+impl Notifier for Matrix {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Matrix
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("MATRIX MESSAGE: {}", event_msg.message);
+        self.outage_threads
+            .send(event_msg, |thread_root| self.send(event_msg, thread_root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn reminders_are_threaded_under_outage() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![
+            TestResponse::new(200, r#"{"event_id":"$root"}"#),
+            TestResponse::new(200, r#"{"event_id":"$reminder"}"#),
+            TestResponse::new(200, r#"{"event_id":"$restored"}"#),
+        ]);
+        let matrix = Matrix::new(&MatrixConfig {
+            homeserver_url: url,
+            room_id: "!room:example.org".to_string(),
+            access_token: "syt_token".into(),
+        })
+        .unwrap();
+        let name = "Google DNS".to_string();
+
+        // Act
+        for event in [
+            Event::ConnectionFailed(30.into()),
+            Event::ConnectionStillDown(3630.into()),
+            Event::ConnectionRestoredAfter(4000.into(), None),
+        ] {
+            matrix
+                .notify(&EventMessage::new(name.clone(), event))
+                .unwrap();
+        }
+
+        // Assert
+        let first = rx.recv().unwrap();
+        assert!(
+            first.request_line.starts_with(
+                "PUT /_matrix/client/v3/rooms/!room:example.org/send/m.room.message/conn_mon."
+            ),
+            "{}",
+            first.request_line
+        );
+        assert_eq!(first.header("Authorization"), Some("Bearer syt_token"));
+        let first: Value = serde_json::from_str(&first.body).unwrap();
+        assert!(first.get("m.relates_to").is_none());
+        for _ in 0..2 {
+            let request: Value = serde_json::from_str(&rx.recv().unwrap().body).unwrap();
+            assert_eq!(request["m.relates_to"]["rel_type"], "m.thread");
+            assert_eq!(request["m.relates_to"]["event_id"], "$root");
+        }
+        assert!(matrix.outage_threads.is_empty());
+    }
+}
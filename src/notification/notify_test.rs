@@ -0,0 +1,127 @@
+use anyhow::bail;
+
+use crate::{event_recorder::EventMessage, secret, state_management::Event};
+
+use super::{discord::Discord, email::Email, Channel, NotificationConfig};
+
+/// Outcome of sending the test message via one channel
+#[derive(Debug)]
+pub(crate) struct ChannelResult {
+    pub(crate) channel: String,
+    /// `None` if the channel does not send test events (eg. exec filtered to other kinds)
+    pub(crate) result: Option<anyhow::Result<()>>,
+}
+
+/// Sends a test message through each configured channel (including those that fail to setup)
+pub(crate) fn send_test_messages(config: &NotificationConfig) -> Vec<ChannelResult> {
+    let event_msg = EventMessage::system_message(Event::Test);
+    let mut result = vec![];
+    if let Some(discord_config) = &config.discord {
+        let event_msg = config.templates.render(Channel::Discord, &event_msg);
+        result.push(ChannelResult {
+            channel: "discord".to_string(),
+            result: Some(
+                Discord::new(discord_config)
+                    .and_then(|discord| discord.send(&event_msg, &event_msg.message)),
+            ),
+        });
+    }
+    if let Some(email_config) = &config.email {
+        let event_msg = config.templates.render(Channel::Email, &event_msg);
+        result.push(ChannelResult {
+            channel: "email".to_string(),
+            result: Some(
+                Email::new(email_config)
+                    .and_then(|email| email.send(&event_msg, &event_msg.message, &[], None)),
+            ),
+        });
+    }
+    for (channel, notifier) in config.try_build_notifiers() {
+        match notifier {
+            Ok(notifier) => {
+                let event_msg = config.templates.render(notifier.channel(), &event_msg);
+                result.push(ChannelResult {
+                    channel: notifier.name().to_string(),
+                    result: notifier
+                        .accepts(event_msg.event.kind())
+                        .then(|| notifier.notify(&event_msg)),
+                });
+            }
+            Err(e) => result.push(ChannelResult {
+                channel: channel.to_string(),
+                result: Some(Err(e.context("failed to setup"))),
+            }),
+        }
+    }
+    result
+}
+
+/// Sends the test messages and prints the result for each channel, fails if any channel failed
+pub(crate) fn run(config: &NotificationConfig) -> anyhow::Result<()> {
+    let results = send_test_messages(config);
+    if results.is_empty() {
+        bail!("no notification channels are configured");
+    }
+    let total = results.len();
+    let mut failed = 0;
+    for ChannelResult { channel, result } in results {
+        // Printed directly so it does not go through the redaction done by the logger
+        let channel = secret::redact(&channel);
+        match result {
+            Some(Ok(())) => println!("OK     {channel}"),
+            None => println!("SKIP   {channel}: does not send test events"),
+            Some(Err(e)) => {
+                failed += 1;
+                println!("FAILED {channel}: {}", secret::redact(&format!("{e:#}")));
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {total} notification channels failed");
+    }
+    println!("All {total} notification channels succeeded");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notification::{
+        test_server::{self, TestResponse},
+        webhook::WebhookConfig,
+    };
+
+    use super::*;
+
+    fn webhook(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            headers: Default::default(),
+            hmac_secret: None,
+            signature_header: "X-Conn-Mon-Signature".to_string(),
+        }
+    }
+
+    #[test]
+    fn reports_each_channel() {
+        // Arrange
+        let (ok_url, ok_rx) = test_server::start(vec![TestResponse::ok()]);
+        let (failing_url, _failing_rx) = test_server::start(vec![TestResponse::new(500, "")]);
+        let config = NotificationConfig {
+            webhooks: vec![webhook(ok_url), webhook(failing_url)],
+            ..Default::default()
+        };
+
+        // Act
+        let actual = send_test_messages(&config);
+
+        // Assert
+        let outcomes: Vec<bool> = actual
+            .iter()
+            .map(|x| x.result.as_ref().is_some_and(|x| x.is_ok()))
+            .collect();
+        assert_eq!(outcomes, [true, false], "{actual:?}");
+        let body: serde_json::Value = serde_json::from_str(&ok_rx.recv().unwrap().body).unwrap();
+        assert_eq!(body["event"], "test");
+        assert!(run(&config).is_err());
+    }
+}
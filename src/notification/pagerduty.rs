@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, warn};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{event_recorder::EventMessage, secret::Secret, state_management::EventKind};
+
+use super::{Channel, Notifier, OutageThreads};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PagerDutyConfig {
+    /// Integration key of the service incidents are opened on
+    pub routing_key: Secret,
+
+    /// Events API v2 endpoint, can be set to any compatible service
+    #[serde(default = "PagerDutyConfig::default_url")]
+    pub url: String,
+
+    /// Severity incidents are triggered with
+    #[serde(default)]
+    pub severity: PagerDutySeverity,
+}
+
+impl PagerDutyConfig {
+    fn default_url() -> String {
+        "https://events.pagerduty.com/v2/enqueue".to_string()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PagerDutySeverity {
+    #[default]
+    Critical,
+    Error,
+    Warning,
+    Info,
+}
+
+pub struct PagerDuty {
+    client: Client,
+    config: PagerDutyConfig,
+    /// The dedup key of the incident of each target's current outage
+    incidents: OutageThreads,
+}
+
+impl PagerDuty {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(config: &PagerDutyConfig) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        Ok(Self {
+            client,
+            config: config.clone(),
+            incidents: Default::default(),
+        })
+    }
+
+    /// Unique per outage of a target, the outage start is used so it does not change with each event
+    fn dedup_key(event_msg: &EventMessage) -> String {
+        // Falls back to when the event happened so unrelated outages never share a key
+        let started = match (
+            event_msg.timestamp.as_date_time(),
+            event_msg.event.duration(),
+        ) {
+            (Some(timestamp), Some(duration)) => (timestamp
+                - chrono::Duration::seconds(duration.as_u64() as i64))
+            .timestamp()
+            .to_string(),
+            (Some(timestamp), None) => timestamp.timestamp().to_string(),
+            (None, _) => event_msg.timestamp.to_string(),
+        };
+        format!("conn_mon/{}/{started}", event_msg.host_disp_name)
+    }
+
+    fn trigger(
+        &self,
+        event_msg: &EventMessage,
+        dedup_key: String,
+        summary: String,
+        severity: PagerDutySeverity,
+    ) -> anyhow::Result<String> {
+        let mut payload = json!({
+            "summary": summary,
+            "source": event_msg.host.as_deref().unwrap_or(&event_msg.host_disp_name),
+            "severity": severity,
+            "component": event_msg.host_disp_name,
+            "custom_details": {
+                "event_kind": event_msg.event.kind(),
+                "message": event_msg.message,
+            },
+        });
+        if let Some(timestamp) = event_msg.timestamp.as_date_time() {
+            payload["timestamp"] = json!(timestamp.to_rfc3339());
+        }
+        if let Some(err_msg) = event_msg.event.error_msg() {
+            payload["custom_details"]["error"] = json!(err_msg);
+        }
+        self.send(json!({
+            "event_action": "trigger",
+            "dedup_key": dedup_key,
+            "payload": payload,
+        }))?;
+        Ok(dedup_key)
+    }
+
+    fn resolve(&self, dedup_key: &str) -> anyhow::Result<()> {
+        self.send(json!({
+            "event_action": "resolve",
+            "dedup_key": dedup_key,
+        }))
+    }
+
+    fn send(&self, mut body: serde_json::Value) -> anyhow::Result<()> {
+        body["routing_key"] = json!(self.config.routing_key.expose());
+        body["client"] = json!("conn_mon");
+        self.client
+            .post(&self.config.url)
+            .json(&body)
+            .send()
+            .context("failed to send request to pagerduty")?
+            .error_for_status()
+            .context("pagerduty responded with an error")?;
+        Ok(())
+    }
+}
+
+impl Notifier for PagerDuty {
+    fn name(&self) -> &str {
+        "pagerduty"
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::PagerDuty
+    }
+
+    fn needs_each_event(&self) -> bool {
+        true
+    }
+
+    /// Reminders are not sent as the incident is already open
+    fn accepts(&self, kind: EventKind) -> bool {
+        matches!(
+            kind,
+            EventKind::Test
+                | EventKind::ConnectionFailed
+                | EventKind::ConnectionError
+                | EventKind::ConnectionRestoredAfter
+        )
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        let kind = event_msg.event.kind();
+        if kind == EventKind::Test {
+            // Resolved right away, it only checks that incidents can be opened
+            let dedup_key = format!("conn_mon/test/{}", event_msg.timestamp);
+            let dedup_key = self.trigger(
+                event_msg,
+                dedup_key,
+                event_msg.text.clone(),
+                PagerDutySeverity::Info,
+            )?;
+            return self.resolve(&dedup_key);
+        }
+        if !self.accepts(kind) {
+            debug!("pagerduty skipped for {kind:?}");
+            return Ok(());
+        }
+        warn!("PAGERDUTY MESSAGE: {}", event_msg.message);
+        self.incidents.send(event_msg, |dedup_key| match kind {
+            EventKind::ConnectionRestoredAfter => {
+                match dedup_key {
+                    Some(dedup_key) => self.resolve(dedup_key)?,
+                    None => warn!(
+                        "no open pagerduty incident known for {:?}, unable to resolve",
+                        event_msg.host_disp_name
+                    ),
+                }
+                Ok(String::new())
+            }
+            _ => self.trigger(
+                event_msg,
+                Self::dedup_key(event_msg),
+                format!("{} is down: {}", event_msg.host_disp_name, event_msg.text),
+                self.config.severity,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn triggers_once_and_resolves_outage() {
+        // Arrange
+        let accepted = r#"{"status":"success","message":"Event processed"}"#;
+        let (url, rx) = test_server::start(vec![
+            TestResponse::new(202, accepted),
+            TestResponse::new(202, accepted),
+        ]);
+        let pagerduty = PagerDuty::new(&PagerDutyConfig {
+            routing_key: "R0UT1NGKEY".into(),
+            url,
+            severity: PagerDutySeverity::Critical,
+        })
+        .unwrap();
+        let name = "Uplink".to_string();
+
+        // Act
+        for event in [
+            Event::ConnectionFailed(30.into()),
+            Event::ConnectionRestoredAfter(4000.into(), None),
+        ] {
+            pagerduty
+                .notify(&EventMessage::new(name.clone(), event))
+                .unwrap();
+        }
+
+        // Assert
+        let trigger: Value = serde_json::from_str(&rx.recv().unwrap().body).unwrap();
+        let resolve: Value = serde_json::from_str(&rx.recv().unwrap().body).unwrap();
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["routing_key"], "R0UT1NGKEY");
+        assert_eq!(trigger["payload"]["severity"], "critical");
+        assert_eq!(trigger["payload"]["source"], "Uplink");
+        assert!(trigger["dedup_key"]
+            .as_str()
+            .unwrap()
+            .starts_with("conn_mon/Uplink/"));
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(resolve["dedup_key"], trigger["dedup_key"]);
+        assert!(pagerduty.incidents.is_empty());
+        assert!(!pagerduty.accepts(EventKind::ConnectionStillDown));
+        assert!(!pagerduty.accepts(EventKind::IAmAlive));
+    }
+
+    #[test]
+    fn dedup_key_without_duration_uses_event_time() {
+        let event_msg = EventMessage::new("Uplink".to_string(), Event::Startup);
+
+        let actual = PagerDuty::dedup_key(&event_msg);
+
+        let event_time = event_msg.timestamp.as_date_time().unwrap().timestamp();
+        assert_eq!(actual, format!("conn_mon/Uplink/{event_time}"));
+    }
+}
@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::warn;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{event_recorder::EventMessage, secret::Secret, state_management::EventKind};
+
+use super::{state_label, Channel, Notifier};
+
+/// Settings for a self hosted push notification service
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "service", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PushConfig {
+    Ntfy {
+        /// Base url of the ntfy server (eg. https://ntfy.sh)
+        server_url: String,
+
+        /// Topic to publish to
+        topic: String,
+
+        /// Credentials if the topic is protected
+        auth: Option<PushAuth>,
+    },
+    Gotify {
+        /// Base url of the gotify server
+        server_url: String,
+
+        /// Application token to publish messages with
+        app_token: Secret,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum PushAuth {
+    Token { token: Secret },
+    Basic { username: String, password: Secret },
+}
+
+/// Priority levels shared by the services, mapped onto each service's own scale
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Priority {
+    Min,
+    Low,
+    Default,
+    High,
+    Urgent,
+}
+
+impl From<EventKind> for Priority {
+    fn from(value: EventKind) -> Self {
+        match value {
+            EventKind::Startup | EventKind::IAmAlive | EventKind::Report => Priority::Min,
+            EventKind::ConnectionRestoredAfter => Priority::Low,
+            // Test messages use the normal priority so they show up like real notifications
+            EventKind::ConnectionStillDown | EventKind::Digest | EventKind::Test => {
+                Priority::Default
+            }
+            EventKind::ConnectionFailed | EventKind::ConnectionError => Priority::High,
+            EventKind::SystemError | EventKind::StillSystemError => Priority::Urgent,
+        }
+    }
+}
+
+impl Priority {
+    /// ntfy uses 1 (min) to 5 (urgent)
+    fn as_ntfy(&self) -> u8 {
+        match self {
+            Priority::Min => 1,
+            Priority::Low => 2,
+            Priority::Default => 3,
+            Priority::High => 4,
+            Priority::Urgent => 5,
+        }
+    }
+
+    /// gotify uses 0 (no notification) to 10 (highest)
+    fn as_gotify(&self) -> u8 {
+        match self {
+            Priority::Min => 0,
+            Priority::Low => 2,
+            Priority::Default => 5,
+            Priority::High => 8,
+            Priority::Urgent => 10,
+        }
+    }
+}
+
+/// Tags for ntfy, the first is an emoji short code that ntfy displays in front of the title
+fn tags(kind: EventKind) -> [&'static str; 2] {
+    let emoji = match kind {
+        EventKind::Startup => "rocket",
+        EventKind::IAmAlive => "heartbeat",
+        EventKind::Report => "bar_chart",
+        EventKind::ConnectionFailed | EventKind::ConnectionError => "rotating_light",
+        EventKind::ConnectionStillDown => "hourglass",
+        EventKind::ConnectionRestoredAfter => "white_check_mark",
+        EventKind::SystemError | EventKind::StillSystemError => "warning",
+        EventKind::Digest => "bookmark_tabs",
+        EventKind::Test => "test_tube",
+    };
+    [emoji, kind.as_str()]
+}
+
+pub struct Push {
+    name: String,
+    client: Client,
+    config: PushConfig,
+}
+
+impl Push {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(config: &PushConfig) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        let name = match config {
+            PushConfig::Ntfy { topic, .. } => format!("ntfy ({topic})"),
+            PushConfig::Gotify { server_url, .. } => format!("gotify ({server_url})"),
+        };
+        Ok(Self {
+            name,
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn build_request(&self, event_msg: &EventMessage) -> RequestBuilder {
+        let EventMessage {
+            host_disp_name: name,
+            event,
+            message,
+            ..
+        } = event_msg;
+        let kind = event.kind();
+        let title = format!("{name} - {}", state_label(kind));
+        let priority = Priority::from(kind);
+        match &self.config {
+            PushConfig::Ntfy {
+                server_url,
+                topic,
+                auth,
+            } => {
+                let request = self
+                    .client
+                    .post(server_url.trim_end_matches('/'))
+                    .json(&json!({
+                        "topic": topic,
+                        "title": title,
+                        "message": message,
+                        "priority": priority.as_ntfy(),
+                        "tags": tags(kind),
+                    }));
+                match auth {
+                    Some(PushAuth::Token { token }) => request.bearer_auth(token.expose()),
+                    Some(PushAuth::Basic { username, password }) => {
+                        request.basic_auth(username, Some(password.expose()))
+                    }
+                    None => request,
+                }
+            }
+            PushConfig::Gotify {
+                server_url,
+                app_token,
+            } => self
+                .client
+                .post(format!("{}/message", server_url.trim_end_matches('/')))
+                .header("X-Gotify-Key", app_token.expose())
+                .json(&json!({
+                    "title": title,
+                    "message": message,
+                    "priority": priority.as_gotify(),
+                })),
+        }
+    }
+}
+
+impl Notifier for Push {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Push
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("PUSH MESSAGE: {}", event_msg.message);
+        self.build_request(event_msg)
+            .send()
+            .with_context(|| format!("failed to send request to {}", self.name))?
+            .error_for_status()
+            .with_context(|| format!("{} responded with an error", self.name))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn ntfy_system_error_is_urgent() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![TestResponse::ok()]);
+        let push = Push::new(&PushConfig::Ntfy {
+            server_url: url,
+            topic: "conn_mon".to_string(),
+            auth: Some(PushAuth::Token {
+                token: "tk_abc".into(),
+            }),
+        })
+        .unwrap();
+        let event_msg = EventMessage::system_message(Event::SystemError("oops".to_string()));
+
+        // Act
+        push.notify(&event_msg).unwrap();
+
+        // Assert
+        let request = rx.recv().unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer tk_abc"));
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["topic"], "conn_mon");
+        assert_eq!(body["priority"], 5);
+        assert_eq!(body["tags"][1], "system_error");
+    }
+
+    #[test]
+    fn gotify_restore_is_low_priority() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![TestResponse::ok()]);
+        let push = Push::new(&PushConfig::Gotify {
+            server_url: format!("{url}/"),
+            app_token: "app_token".into(),
+        })
+        .unwrap();
+        let event_msg = EventMessage::new(
+            "Google DNS".to_string(),
+            Event::ConnectionRestoredAfter(45.into(), None),
+        );
+
+        // Act
+        push.notify(&event_msg).unwrap();
+
+        // Assert
+        let request = rx.recv().unwrap();
+        assert_eq!(request.request_line, "POST /message HTTP/1.1");
+        assert_eq!(request.header("X-Gotify-Key"), Some("app_token"));
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["priority"], 2);
+        assert_eq!(body["title"], "Google DNS - Up");
+        assert_eq!(body["message"], event_msg.message);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state_management::EventKind;
+
+use super::Channel;
+
+/// Sends the events of some targets to specific channels instead of all of them
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Display names of the targets this rule applies to
+    #[serde(default)]
+    pub targets: Vec<String>,
+
+    /// Tags of the targets this rule applies to. If neither targets nor tags are set it applies to all
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// If set only events of these kinds match this rule
+    pub event_kinds: Option<Vec<EventKind>>,
+
+    /// Channels that matching events are sent to
+    pub channels: Vec<Channel>,
+
+    /// If set matching events are emailed to these addresses instead of the configured recipients
+    pub email_to: Option<Vec<String>>,
+}
+
+impl RouteConfig {
+    fn matches(&self, name: &str, tags: &[String], kind: EventKind) -> bool {
+        let target_matches = (self.targets.is_empty() && self.tags.is_empty())
+            || self.targets.iter().any(|x| x == name)
+            || self.tags.iter().any(|x| tags.contains(x));
+        let kind_matches = match &self.event_kinds {
+            Some(kinds) => kinds.contains(&kind),
+            None => true,
+        };
+        target_matches && kind_matches
+    }
+}
+
+/// Where an event should be sent based on the routing rules
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Route {
+    /// `None` if no rule matched and the event goes out as usual (discord with email fallback and all others)
+    channels: Option<BTreeSet<Channel>>,
+    email_to: Option<Vec<String>>,
+}
+
+impl Route {
+    /// Combines all the rules that match, the event is sent to every channel any of them lists
+    pub(crate) fn find(
+        rules: &[RouteConfig],
+        name: &str,
+        tags: &[String],
+        kind: EventKind,
+    ) -> Self {
+        let mut result = Self::default();
+        for rule in rules.iter().filter(|x| x.matches(name, tags, kind)) {
+            result
+                .channels
+                .get_or_insert_with(Default::default)
+                .extend(rule.channels.iter().copied());
+            if let Some(email_to) = &rule.email_to {
+                let addresses = result.email_to.get_or_insert_with(Vec::new);
+                for address in email_to {
+                    if !addresses.contains(address) {
+                        addresses.push(address.clone());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub(crate) fn allows(&self, channel: Channel) -> bool {
+        match &self.channels {
+            Some(channels) => channels.contains(&channel),
+            None => true,
+        }
+    }
+
+    /// True if a rule sent the event to `channel` (as opposed to it being used by default)
+    pub(crate) fn is_explicit(&self, channel: Channel) -> bool {
+        self.channels.as_ref().is_some_and(|x| x.contains(&channel))
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.channels.is_none()
+    }
+
+    pub(crate) fn email_to(&self) -> Option<&[String]> {
+        self.email_to.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<RouteConfig> {
+        vec![
+            RouteConfig {
+                targets: vec![],
+                tags: vec!["lab-network".to_string()],
+                event_kinds: None,
+                channels: vec![Channel::Matrix, Channel::Email],
+                email_to: Some(vec!["lab@example.com".to_string()]),
+            },
+            RouteConfig {
+                targets: vec!["Uplink".to_string()],
+                tags: vec![],
+                event_kinds: Some(vec![EventKind::ConnectionFailed]),
+                channels: vec![Channel::Discord, Channel::Slack],
+                email_to: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn tagged_target_goes_only_to_its_channels() {
+        let actual = Route::find(
+            &rules(),
+            "Lab Switch",
+            &["lab-network".to_string()],
+            EventKind::ConnectionStillDown,
+        );
+
+        assert!(actual.is_explicit(Channel::Matrix));
+        assert!(!actual.allows(Channel::Discord));
+        assert_eq!(
+            actual.email_to(),
+            Some(&["lab@example.com".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn unmatched_event_kind_uses_default() {
+        let actual = Route::find(&rules(), "Uplink", &[], EventKind::ConnectionRestoredAfter);
+
+        assert!(actual.is_default());
+        assert!(actual.allows(Channel::Telegram));
+    }
+
+    #[test]
+    fn matching_name_and_kind() {
+        let actual = Route::find(&rules(), "Uplink", &[], EventKind::ConnectionFailed);
+
+        assert!(actual.is_explicit(Channel::Slack));
+        assert!(!actual.allows(Channel::Email));
+        assert_eq!(actual.email_to(), None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use log::{error, warn};
+use reqwest::{
+    blocking::{Client, Response},
+    header::RETRY_AFTER,
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{event_recorder::EventMessage, secret::Secret, state_management::VERSION, Seconds};
+
+use super::{event_color, state_label, Channel, Notifier};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig {
+    /// Incoming webhook url provided by slack (https://hooks.slack.com/services/...)
+    pub webhook_url: Secret,
+}
+
+pub struct Slack {
+    client: Client,
+    url: String,
+}
+
+impl Slack {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+    const RETRY_ATTEMPTS: u8 = 3;
+    /// Used if slack rate limits us without saying how long to wait
+    const DEFAULT_RETRY_AFTER: Seconds = Seconds::new(1);
+    /// Upper bound on how long we are willing to wait when rate limited
+    const MAX_RETRY_AFTER: Seconds = Seconds::new(60);
+
+    pub fn new(config: &SlackConfig) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        Ok(Self {
+            client,
+            url: config.webhook_url.expose().to_string(),
+        })
+    }
+
+    fn build_payload(event_msg: &EventMessage) -> Value {
+        let EventMessage {
+            host_disp_name: name,
+            timestamp,
+            event,
+            text,
+            message,
+            ..
+        } = event_msg;
+        let duration = match event.duration() {
+            Some(duration) => duration.to_string(),
+            None => "-".to_string(),
+        };
+        json!({
+            "text": message.clone(),
+            "attachments": [{
+                "color": format!("#{:06X}", event_color(event.kind())),
+                "blocks": [
+                    {
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": format!("*{name}*\n{text}") }
+                    },
+                    {
+                        "type": "section",
+                        "fields": [
+                            { "type": "mrkdwn", "text": format!("*Target*\n{name}") },
+                            { "type": "mrkdwn", "text": format!("*State*\n{}", state_label(event.kind())) },
+                            { "type": "mrkdwn", "text": format!("*Duration*\n{duration}") },
+                        ]
+                    },
+                    {
+                        "type": "context",
+                        "elements": [
+                            { "type": "mrkdwn", "text": format!("{timestamp} | conn_mon {VERSION}") }
+                        ]
+                    }
+                ]
+            }]
+        })
+    }
+
+    /// How long slack asked us to wait before trying again
+    fn retry_after(response: &Response) -> Seconds {
+        let result = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Seconds::from)
+            .unwrap_or(Self::DEFAULT_RETRY_AFTER);
+        result.min(Self::MAX_RETRY_AFTER)
+    }
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Slack
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("SLACK MESSAGE: {}", event_msg.message);
+        let payload = Self::build_payload(event_msg);
+        for i in 0..Self::RETRY_ATTEMPTS {
+            let response = self
+                .client
+                .post(&self.url)
+                .json(&payload)
+                .send()
+                .context("failed to send request to slack")?;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                let wait = Self::retry_after(&response);
+                error!(
+                    "attempt #{} to send via slack was rate limited. Retrying after {wait}",
+                    i + 1
+                );
+                std::thread::sleep(wait.into());
+                continue;
+            }
+            response
+                .error_for_status()
+                .context("slack responded with an error")?;
+            return Ok(());
+        }
+        bail!(
+            "failed to send via slack after {} attempts due to rate limiting",
+            Self::RETRY_ATTEMPTS
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn retries_after_rate_limit() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![
+            TestResponse::new(429, "rate_limited").with_header("Retry-After", "0"),
+            TestResponse::new(200, "ok"),
+        ]);
+        let slack = Slack::new(&SlackConfig {
+            webhook_url: url.as_str().into(),
+        })
+        .unwrap();
+        let event_msg = EventMessage::new(
+            "Google DNS".to_string(),
+            Event::ConnectionRestoredAfter(90.into(), None),
+        );
+
+        // Act
+        let actual = slack.notify(&event_msg);
+
+        // Assert
+        assert!(actual.is_ok(), "{actual:?}");
+        let _rate_limited = rx.recv().unwrap();
+        let request = rx.recv().unwrap();
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        let attachment = &body["attachments"][0];
+        assert_eq!(attachment["color"], "#43A047");
+        assert_eq!(
+            attachment["blocks"][1]["fields"][2]["text"],
+            "*Duration*\n0 days 00:01:30"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    net::UdpSocket,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use chrono::{Local, SecondsFormat};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{event_recorder::EventMessage, state_management::EventKind};
+
+use super::{Channel, Notifier};
+
+/// Identifier used for the application in both journald and syslog
+const IDENTIFIER: &str = "conn_mon";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "format", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SyslogConfig {
+    /// Structured entries sent using the journald native protocol
+    Journald {
+        #[serde(default = "SyslogConfig::default_journald_socket")]
+        socket_path: PathBuf,
+    },
+    /// RFC 5424 formatted messages with the event details as structured data
+    Rfc5424 {
+        #[serde(default = "SyslogConfig::default_destination")]
+        destination: SyslogDestination,
+
+        /// Syslog facility code (Default is 3 for daemon)
+        #[serde(default = "SyslogConfig::default_facility")]
+        facility: u8,
+    },
+}
+
+impl SyslogConfig {
+    fn default_journald_socket() -> PathBuf {
+        PathBuf::from("/run/systemd/journal/socket")
+    }
+
+    fn default_destination() -> SyslogDestination {
+        SyslogDestination::Unix(PathBuf::from("/dev/log"))
+    }
+
+    fn default_facility() -> u8 {
+        3
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogDestination {
+    /// Path of a unix datagram socket
+    Unix(PathBuf),
+    /// Address (host:port) to send UDP datagrams to
+    Udp(String),
+}
+
+enum Socket {
+    Unix { socket: UnixDatagram, path: PathBuf },
+    Udp { socket: UdpSocket, address: String },
+}
+
+impl Socket {
+    fn new(destination: &SyslogDestination) -> anyhow::Result<Self> {
+        Ok(match destination {
+            SyslogDestination::Unix(path) => Self::Unix {
+                socket: UnixDatagram::unbound().context("failed to create unix socket")?,
+                path: path.clone(),
+            },
+            SyslogDestination::Udp(address) => Self::Udp {
+                socket: UdpSocket::bind("0.0.0.0:0").context("failed to bind udp socket")?,
+                address: address.clone(),
+            },
+        })
+    }
+
+    fn send(&self, data: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Socket::Unix { socket, path } => {
+                socket
+                    .send_to(data, path)
+                    .with_context(|| format!("failed to send to {path:?}"))?;
+            }
+            Socket::Udp { socket, address } => {
+                socket
+                    .send_to(data, address)
+                    .with_context(|| format!("failed to send to {address:?}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Syslog {
+    name: String,
+    socket: Socket,
+    format: Format,
+}
+
+enum Format {
+    Journald,
+    Rfc5424 { facility: u8, hostname: String },
+}
+
+impl Syslog {
+    pub fn new(config: &SyslogConfig) -> anyhow::Result<Self> {
+        Ok(match config {
+            SyslogConfig::Journald { socket_path } => Self {
+                name: "journald".to_string(),
+                socket: Socket::new(&SyslogDestination::Unix(socket_path.clone()))?,
+                format: Format::Journald,
+            },
+            SyslogConfig::Rfc5424 {
+                destination,
+                facility,
+            } => {
+                if *facility > 23 {
+                    bail!("syslog facility must be between 0 and 23 but got {facility}");
+                }
+                Self {
+                    name: "syslog".to_string(),
+                    socket: Socket::new(destination)?,
+                    format: Format::Rfc5424 {
+                        facility: *facility,
+                        hostname: hostname(),
+                    },
+                }
+            }
+        })
+    }
+
+    /// Syslog severity (0 emergency to 7 debug) for the event
+    fn severity(kind: EventKind) -> u8 {
+        match kind {
+            EventKind::Startup | EventKind::IAmAlive | EventKind::Report => 6,
+            EventKind::ConnectionRestoredAfter | EventKind::Digest | EventKind::Test => 5,
+            EventKind::ConnectionStillDown => 4,
+            EventKind::ConnectionFailed | EventKind::ConnectionError => 3,
+            EventKind::SystemError | EventKind::StillSystemError => 2,
+        }
+    }
+
+    /// How long the target has been (or was) down, other events like keep alive messages hold
+    /// the uptime instead
+    fn outage_seconds(event_msg: &EventMessage) -> Option<u64> {
+        match event_msg.event.kind() {
+            EventKind::ConnectionFailed
+            | EventKind::ConnectionError
+            | EventKind::ConnectionStillDown
+            | EventKind::ConnectionRestoredAfter
+            | EventKind::StillSystemError => event_msg.event.duration().map(|x| x.as_u64()),
+            EventKind::Startup
+            | EventKind::IAmAlive
+            | EventKind::Report
+            | EventKind::SystemError
+            | EventKind::Digest
+            | EventKind::Test => None,
+        }
+    }
+
+    /// See https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+    fn journald_entry(event_msg: &EventMessage) -> Vec<u8> {
+        let EventMessage {
+            host_disp_name: name,
+            event,
+            message,
+            ..
+        } = event_msg;
+        let mut fields = vec![
+            ("MESSAGE", message.clone()),
+            ("PRIORITY", Self::severity(event.kind()).to_string()),
+            ("SYSLOG_IDENTIFIER", IDENTIFIER.to_string()),
+            ("TARGET", name.clone()),
+            ("EVENT_KIND", event.kind().as_str().to_string()),
+        ];
+        if let Some(secs) = Self::outage_seconds(event_msg) {
+            fields.push(("OUTAGE_SECONDS", secs.to_string()));
+        }
+        let mut result = vec![];
+        for (key, value) in fields {
+            result.extend_from_slice(key.as_bytes());
+            if value.contains('\n') {
+                // Values with new lines must be sent with their length instead of using `=`
+                result.push(b'\n');
+                result.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            } else {
+                result.push(b'=');
+            }
+            result.extend_from_slice(value.as_bytes());
+            result.push(b'\n');
+        }
+        result
+    }
+
+    fn rfc5424_message(facility: u8, hostname: &str, event_msg: &EventMessage) -> String {
+        let EventMessage {
+            host_disp_name: name,
+            event,
+            message,
+            ..
+        } = event_msg;
+        let priority = facility * 8 + Self::severity(event.kind());
+        let time = Local::now().to_rfc3339_opts(SecondsFormat::Millis, false);
+        let mut structured_data = format!(
+            "[{IDENTIFIER}@32473 target=\"{}\" event_kind=\"{}\"",
+            escape_param(name),
+            event.kind().as_str()
+        );
+        if let Some(secs) = Self::outage_seconds(event_msg) {
+            structured_data.push_str(&format!(" outage_seconds=\"{secs}\""));
+        }
+        structured_data.push(']');
+        format!(
+            "<{priority}>1 {time} {hostname} {IDENTIFIER} {} {} {structured_data} {message}",
+            std::process::id(),
+            event.kind().as_str()
+        )
+    }
+}
+
+impl Notifier for Syslog {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Syslog
+    }
+
+    fn needs_each_event(&self) -> bool {
+        true
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("SYSLOG MESSAGE: {}", event_msg.message);
+        let data = match &self.format {
+            Format::Journald => Self::journald_entry(event_msg),
+            Format::Rfc5424 { facility, hostname } => {
+                Self::rfc5424_message(*facility, hostname, event_msg).into_bytes()
+            }
+        };
+        self.socket.send(&data)
+    }
+}
+
+/// Escapes a structured data parameter value as required by RFC 5424
+fn escape_param(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn hostname() -> String {
+    let path = Path::new("/proc/sys/kernel/hostname");
+    match std::fs::read_to_string(path) {
+        Ok(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => "-".to_string(), // NILVALUE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::state_management::Event;
+
+    use super::*;
+
+    fn restored_event() -> EventMessage {
+        EventMessage::new(
+            "Lab \"A\"".to_string(),
+            Event::ConnectionRestoredAfter(120.into(), None),
+        )
+    }
+
+    #[test]
+    fn journald_entry_has_fields() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("journal.socket");
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+        let syslog = Syslog::new(&SyslogConfig::Journald { socket_path }).unwrap();
+
+        // Act
+        syslog.notify(&restored_event()).unwrap();
+
+        // Assert
+        let mut buf = [0; 4096];
+        let len = receiver.recv(&mut buf).unwrap();
+        let entry = String::from_utf8_lossy(&buf[..len]).to_string();
+        assert!(entry.contains("TARGET=Lab \"A\"\n"), "{entry}");
+        assert!(entry.contains("EVENT_KIND=connection_restored_after\n"));
+        assert!(entry.contains("OUTAGE_SECONDS=120\n"));
+        assert!(entry.contains("PRIORITY=5\n"));
+    }
+
+    #[test]
+    fn keep_alive_has_no_outage_seconds() {
+        // Arrange
+        let event_msg = EventMessage::system_message(Event::IAmAlive(86400.into()));
+
+        // Act
+        let journald = String::from_utf8(Syslog::journald_entry(&event_msg)).unwrap();
+        let rfc5424 = Syslog::rfc5424_message(3, "host", &event_msg);
+
+        // Assert
+        assert!(!journald.contains("OUTAGE_SECONDS"), "{journald}");
+        assert!(!rfc5424.contains("outage_seconds"), "{rfc5424}");
+    }
+
+    #[test]
+    fn rfc5424_over_udp() {
+        // Arrange
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let syslog = Syslog::new(&SyslogConfig::Rfc5424 {
+            destination: SyslogDestination::Udp(receiver.local_addr().unwrap().to_string()),
+            facility: 3,
+        })
+        .unwrap();
+
+        // Act
+        syslog.notify(&restored_event()).unwrap();
+
+        // Assert
+        let mut buf = [0; 4096];
+        let len = receiver.recv(&mut buf).unwrap();
+        let msg = String::from_utf8_lossy(&buf[..len]).to_string();
+        assert!(msg.starts_with("<29>1 "), "{msg}");
+        assert!(
+            msg.contains(r#"[conn_mon@32473 target="Lab \"A\"" event_kind="connection_restored_after" outage_seconds="120"]"#),
+            "{msg}"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use log::{error, warn};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{event_recorder::EventMessage, secret::Secret, state_management::EventKind};
+
+use super::{Channel, Notifier};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    /// Token of the bot as provided by BotFather
+    pub bot_token: Secret,
+
+    /// Chats to send the messages to, either numeric IDs or `@channelusername`
+    pub chat_ids: Vec<ChatId>,
+
+    /// Base url of the Bot API, only expected to be changed for testing or a self hosted API server
+    #[serde(default = "TelegramConfig::default_api_base_url")]
+    pub api_base_url: String,
+}
+
+impl TelegramConfig {
+    fn default_api_base_url() -> String {
+        "https://api.telegram.org".to_string()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Username(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+pub struct Telegram {
+    client: Client,
+    send_url: String,
+    chat_ids: Vec<ChatId>,
+}
+
+impl Telegram {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(config: &TelegramConfig) -> anyhow::Result<Self> {
+        if config.chat_ids.is_empty() {
+            bail!("no chat ids set for telegram");
+        }
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        Ok(Self {
+            client,
+            send_url: format!(
+                "{}/bot{}/sendMessage",
+                config.api_base_url.trim_end_matches('/'),
+                config.bot_token.expose()
+            ),
+            chat_ids: config.chat_ids.clone(),
+        })
+    }
+
+    /// Reminders are delivered without a sound, everything else alerts the user
+    fn is_silent(kind: EventKind) -> bool {
+        match kind {
+            EventKind::IAmAlive
+            | EventKind::Report
+            | EventKind::ConnectionStillDown
+            | EventKind::StillSystemError => true,
+            EventKind::Startup
+            | EventKind::ConnectionFailed
+            | EventKind::ConnectionError
+            | EventKind::ConnectionRestoredAfter
+            | EventKind::SystemError
+            | EventKind::Digest
+            | EventKind::Test => false,
+        }
+    }
+
+    fn send_to_chat(&self, chat_id: &ChatId, event_msg: &EventMessage) -> anyhow::Result<()> {
+        // Sent as plain text (no `parse_mode`) so the message is shown exactly as rendered
+        let payload = json!({
+            "chat_id": chat_id,
+            "text": event_msg.message,
+            "disable_notification": Self::is_silent(event_msg.event.kind()),
+        });
+        // Urls are removed from errors as they contain the bot token
+        let response = self
+            .client
+            .post(&self.send_url)
+            .json(&payload)
+            .send()
+            .map_err(|e| e.without_url())
+            .context("failed to send request to telegram")?;
+        let status = response.status();
+        let api_response: ApiResponse = response
+            .json()
+            .map_err(|e| e.without_url())
+            .with_context(|| format!("failed to parse telegram response. Status: {status}"))?;
+        if !api_response.ok {
+            return Err(anyhow!(
+                "telegram rejected message with status {status}: {}",
+                api_response.description.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Telegram
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("TELEGRAM MESSAGE: {}", event_msg.message);
+        let mut failed_count = 0;
+        for chat_id in self.chat_ids.iter() {
+            if let Err(e) = self.send_to_chat(chat_id, event_msg) {
+                error!("failed to send to telegram chat {chat_id:?}: {e:?}");
+                failed_count += 1;
+            }
+        }
+        if failed_count > 0 {
+            bail!(
+                "failed to send to {failed_count} of {} telegram chats",
+                self.chat_ids.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::Event,
+    };
+
+    use super::*;
+
+    #[test]
+    fn reminder_is_sent_silently() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![TestResponse::new(200, r#"{"ok":true}"#)]);
+        let telegram = Telegram::new(&TelegramConfig {
+            bot_token: "123:abc".into(),
+            chat_ids: vec![ChatId::Id(-100)],
+            api_base_url: url,
+        })
+        .unwrap();
+        let mut event_msg = EventMessage::new(
+            "Google DNS".to_string(),
+            Event::ConnectionStillDown(3600.into()),
+        );
+        event_msg.message = "Still down (1h) *Google_DNS*".to_string();
+
+        // Act
+        let actual = telegram.notify(&event_msg);
+
+        // Assert
+        assert!(actual.is_ok(), "{actual:?}");
+        let request = rx.recv().unwrap();
+        assert_eq!(
+            request.request_line,
+            "POST /bot123:abc/sendMessage HTTP/1.1"
+        );
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["chat_id"], -100);
+        assert_eq!(body["disable_notification"], true);
+        assert!(body.get("parse_mode").is_none(), "{body}");
+        assert_eq!(body["text"], "Still down (1h) *Google_DNS*");
+    }
+
+    #[test]
+    fn api_error_is_reported() {
+        // Arrange
+        let (url, _rx) = test_server::start(vec![TestResponse::new(
+            400,
+            r#"{"ok":false,"description":"Bad Request: chat not found"}"#,
+        )]);
+        let telegram = Telegram::new(&TelegramConfig {
+            bot_token: "123:abc".into(),
+            chat_ids: vec![ChatId::Username("@missing".to_string())],
+            api_base_url: url,
+        })
+        .unwrap();
+        let event_msg =
+            EventMessage::new("Google DNS".to_string(), Event::ConnectionFailed(30.into()));
+
+        // Act
+        let actual = telegram.send_to_chat(&telegram.chat_ids[0], &event_msg);
+
+        // Assert
+        let err = format!("{:?}", actual.unwrap_err());
+        assert!(err.contains("chat not found"), "{err}");
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::bail;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    event_recorder::EventMessage,
+    state_management::{Event, EventKind, VERSION},
+};
+
+use super::Channel;
+
+/// Text with `{placeholder}`s that are filled in from the event when a notification is sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template(String);
+
+impl Template {
+    /// `{event}` is the wording of the event (or its kind when used in an event template)
+    const PLACEHOLDERS: [&'static str; 9] = [
+        "target",
+        "host",
+        "duration",
+        "error",
+        "uptime",
+        "version",
+        "timestamp",
+        "event",
+        "event_kind",
+    ];
+
+    pub(crate) fn render(&self, event_msg: &EventMessage, event_text: &str) -> String {
+        let event = &event_msg.event;
+        let mut result = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+        while let Some((before, name, after)) = next_placeholder(rest) {
+            result.push_str(before);
+            match name {
+                "target" => result.push_str(&event_msg.host_disp_name),
+                "host" => result.push_str(event_msg.host.as_deref().unwrap_or_default()),
+                "duration" => {
+                    if let Some(duration) = event.duration() {
+                        result.push_str(&duration.to_string());
+                    }
+                }
+                // Only events about the monitor itself have an uptime
+                "uptime" => match event {
+                    Event::IAmAlive(uptime) => result.push_str(&uptime.to_string()),
+                    Event::Report(report) => result.push_str(&report.uptime.to_string()),
+                    _ => {}
+                },
+                "error" => result.push_str(event.error_msg().unwrap_or_default()),
+                "version" => result.push_str(VERSION),
+                "timestamp" => result.push_str(&event_msg.timestamp.to_string()),
+                "event" => result.push_str(event_text),
+                "event_kind" => result.push_str(event.kind().as_str()),
+                _ => unreachable!("placeholders are checked when the template is created"),
+            }
+            rest = after;
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut rest = value.as_str();
+        while let Some((_, name, after)) = next_placeholder(rest) {
+            if !Self::PLACEHOLDERS.contains(&name) {
+                bail!(
+                    "unknown placeholder {{{name}}} in template {value:?}. Expected one of: {}",
+                    Self::PLACEHOLDERS.map(|x| format!("{{{x}}}")).join(", ")
+                );
+            }
+            rest = after;
+        }
+        Ok(Self(value))
+    }
+}
+
+impl From<Template> for String {
+    fn from(value: Template) -> Self {
+        value.0
+    }
+}
+
+/// Splits out the first `{name}` returning the text before, the name and the text after it.
+/// Braces that do not surround a name made of lowercase letters and underscores are left as is.
+fn next_placeholder(text: &str) -> Option<(&str, &str, &str)> {
+    let mut search_from = 0;
+    while let Some(start) = text[search_from..].find('{').map(|i| i + search_from) {
+        let after_open = &text[start + 1..];
+        let name_len = after_open
+            .find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(after_open.len());
+        if name_len > 0 && after_open[name_len..].starts_with('}') {
+            return Some((
+                &text[..start],
+                &after_open[..name_len],
+                &after_open[name_len + 1..],
+            ));
+        }
+        search_from = start + 1;
+    }
+    None
+}
+
+/// Templates that replace the built in wording of notifications
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    /// Wording used for each kind of event, kinds not listed keep the built in wording
+    #[serde(default)]
+    pub events: HashMap<EventKind, Template>,
+
+    /// The full message sent (Default is "{timestamp} - {target} - {event}")
+    pub message: Option<Template>,
+
+    /// Overrides for specific channels, these take precedence over the templates above
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "TemplateConfig::deserialize_channels"
+    )]
+    pub channels: BTreeMap<Channel, TemplateConfig>,
+}
+
+impl TemplateConfig {
+    /// Channel overrides only apply one level deep so they cannot have channels of their own
+    fn deserialize_channels<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<Channel, TemplateConfig>, D::Error> {
+        let result = BTreeMap::<Channel, TemplateConfig>::deserialize(deserializer)?;
+        if let Some(channel) = result.keys().find(|x| !result[x].channels.is_empty()) {
+            return Err(D::Error::custom(format!(
+                "templates for {channel:?} cannot have channels of their own"
+            )));
+        }
+        Ok(result)
+    }
+
+    /// Returns a copy of the event message with the text as it should be sent via `channel`
+    pub(crate) fn render(&self, channel: Channel, event_msg: &EventMessage) -> EventMessage {
+        let overrides = self.channels.get(&channel);
+        let kind = event_msg.event.kind();
+        let mut result = event_msg.clone();
+        let event_template = overrides
+            .and_then(|x| x.events.get(&kind))
+            .or_else(|| self.events.get(&kind));
+        if let Some(template) = event_template {
+            result.text = template.render(event_msg, kind.as_str());
+        }
+        let message_template = overrides
+            .and_then(|x| x.message.as_ref())
+            .or(self.message.as_ref());
+        if let Some(template) = message_template {
+            result.message = template.render(event_msg, &result.text);
+        } else if event_template.is_some() {
+            result.message = EventMessage::default_message(&result);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn template(value: &str) -> Template {
+        Template::try_from(value.to_string()).unwrap()
+    }
+
+    fn error_event() -> EventMessage {
+        EventMessage::new(
+            "Google DNS".to_string(),
+            Event::ConnectionError(45.into(), "Destination Host Unreachable".to_string()),
+        )
+        .with_host("8.8.8.8".to_string())
+    }
+
+    #[test]
+    fn defaults_are_unchanged() {
+        let event_msg = error_event();
+
+        let actual = TemplateConfig::default().render(Channel::Slack, &event_msg);
+
+        assert_eq!(actual.text, event_msg.event.to_string());
+        assert_eq!(
+            actual.message,
+            format!("{} - Google DNS - {}", event_msg.timestamp, event_msg.event)
+        );
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        // Arrange
+        let config = TemplateConfig {
+            events: HashMap::from([(
+                EventKind::ConnectionError,
+                template("{target} ({host}) down for {duration}: {error}"),
+            )]),
+            message: Some(template("[{version}] {event} {}")),
+            channels: Default::default(),
+        };
+
+        // Act
+        let actual = config.render(Channel::Discord, &error_event());
+
+        // Assert
+        assert_eq!(
+            actual.text,
+            "Google DNS (8.8.8.8) down for 0 days 00:00:45: Destination Host Unreachable"
+        );
+        assert!(actual
+            .message
+            .starts_with(&format!("[{VERSION}] Google DNS (8.8.8.8)")));
+    }
+
+    #[test]
+    fn channel_overrides_take_precedence() {
+        // Arrange
+        let config = TemplateConfig {
+            events: HashMap::from([(EventKind::ConnectionError, template("global"))]),
+            message: None,
+            channels: BTreeMap::from([(
+                Channel::Telegram,
+                TemplateConfig {
+                    events: HashMap::from([(EventKind::ConnectionError, template("telegram"))]),
+                    message: Some(template("{event}!")),
+                    channels: Default::default(),
+                },
+            )]),
+        };
+        let event_msg = error_event();
+
+        // Act
+        let telegram = config.render(Channel::Telegram, &event_msg);
+        let slack = config.render(Channel::Slack, &event_msg);
+
+        // Assert
+        assert_eq!(telegram.message, "telegram!");
+        assert_eq!(
+            slack.message,
+            format!("{} - Google DNS - global", event_msg.timestamp)
+        );
+    }
+
+    #[rstest]
+    #[case(Event::IAmAlive(90.into()), "up 0 days 00:01:30")]
+    #[case(Event::ConnectionRestoredAfter(45.into(), None), "up ")]
+    fn uptime_only_for_monitor_events(#[case] event: Event, #[case] expected: &str) {
+        let event_msg = EventMessage::new("Google DNS".to_string(), event);
+
+        let actual = template("up {uptime}").render(&event_msg, "");
+
+        assert_eq!(actual, expected);
+    }
+
+    #[rstest]
+    #[case("{target} is {status}", false)]
+    #[case("{target} {Literal}", true)]
+    #[case("{ not a placeholder }", true)]
+    fn validates_placeholders(#[case] value: &str, #[case] expected: bool) {
+        let actual = Template::try_from(value.to_string());
+
+        assert_eq!(actual.is_ok(), expected, "{actual:?}");
+    }
+
+    #[test]
+    fn loads_from_json() {
+        let actual: TemplateConfig = serde_json::from_str(
+            r#"{
+                "events": { "connection_failed": "{target} is down" },
+                "channels": { "email": { "message": "{event}" } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            actual.events[&EventKind::ConnectionFailed],
+            template("{target} is down")
+        );
+        assert!(actual.channels[&Channel::Email].message.is_some());
+    }
+
+    #[test]
+    fn nested_channels_are_rejected() {
+        let actual = serde_json::from_str::<TemplateConfig>(
+            r#"{ "channels": { "email": { "channels": { "slack": { "message": "{event}" } } } } }"#,
+        );
+
+        let err = actual.unwrap_err().to_string();
+        assert!(err.contains("cannot have channels"), "{err}");
+    }
+}
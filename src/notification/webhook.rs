@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use log::warn;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{event_recorder::EventMessage, secret::Secret};
+
+use super::{Channel, EventPayload, Notifier};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Url the JSON payload is posted to
+    pub url: String,
+
+    /// Extra headers to include with each request (eg. for authorization)
+    #[serde(default)]
+    pub headers: BTreeMap<String, Secret>,
+
+    /// If set the body is signed with HMAC-SHA256 using this key
+    pub hmac_secret: Option<Secret>,
+
+    /// Header the signature is sent in, formatted as `sha256=<hex digest>`
+    #[serde(default = "WebhookConfig::default_signature_header")]
+    pub signature_header: String,
+}
+
+impl WebhookConfig {
+    fn default_signature_header() -> String {
+        "X-Conn-Mon-Signature".to_string()
+    }
+}
+
+pub struct Webhook {
+    name: String,
+    client: Client,
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(config: &WebhookConfig) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .context("failed to build http client")?;
+        Ok(Self {
+            name: format!("webhook ({})", config.url),
+            client,
+            config: config.clone(),
+        })
+    }
+
+    fn sign(secret: &str, body: &[u8]) -> anyhow::Result<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .context("failed to create hmac from secret")?;
+        mac.update(body);
+        Ok(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn channel(&self) -> Channel {
+        Channel::Webhook
+    }
+
+    fn needs_each_event(&self) -> bool {
+        true
+    }
+
+    fn notify(&self, event_msg: &EventMessage) -> anyhow::Result<()> {
+        warn!("WEBHOOK MESSAGE: {}", event_msg.message);
+        let body = serde_json::to_vec(&EventPayload::from(event_msg))
+            .context("failed to serialize webhook payload")?;
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        for (key, value) in self.config.headers.iter() {
+            request = request.header(key, value.expose());
+        }
+        if let Some(secret) = &self.config.hmac_secret {
+            request = request.header(
+                &self.config.signature_header,
+                Self::sign(secret.expose(), &body)?,
+            );
+        }
+        request
+            .body(body)
+            .send()
+            .context("failed to send request to webhook")?
+            .error_for_status()
+            .context("webhook responded with an error")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        notification::test_server::{self, TestResponse},
+        state_management::{Event, VERSION},
+    };
+
+    use super::*;
+
+    #[test]
+    fn posts_signed_payload() {
+        // Arrange
+        let (url, rx) = test_server::start(vec![TestResponse::ok()]);
+        let config = WebhookConfig {
+            url: format!("{url}/hook"),
+            headers: [("X-Team".to_string(), "ops".into())].into(),
+            hmac_secret: Some("secret".into()),
+            signature_header: WebhookConfig::default_signature_header(),
+        };
+        let webhook = Webhook::new(&config).unwrap();
+        let event_msg =
+            EventMessage::new("Google DNS".to_string(), Event::ConnectionFailed(30.into()));
+
+        // Act
+        webhook.notify(&event_msg).unwrap();
+
+        // Assert
+        let request = rx.recv().unwrap();
+        assert_eq!(request.request_line, "POST /hook HTTP/1.1");
+        assert_eq!(request.header("X-Team"), Some("ops"));
+        assert_eq!(
+            request.header("X-Conn-Mon-Signature").unwrap(),
+            Webhook::sign("secret", request.body.as_bytes()).unwrap()
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["target"], "Google DNS");
+        assert_eq!(body["event"], "connection_failed");
+        assert_eq!(body["duration_secs"], 30);
+        assert_eq!(body["version"], VERSION);
+    }
+
+    #[test]
+    fn signature_matches_known_value() {
+        // Value from `echo -n 'hello' | openssl dgst -sha256 -hmac 'key'`
+        let expected = "sha256=9307b3b915efb5171ff14d8cb55fbcc798c6c0ef1456d66ded1a6aa723a58b7b";
+
+        let actual = Webhook::sign("key", b"hello").unwrap();
+
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{create_dir_all, File},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_recorder::{TargetHandler, Timestamp},
+    ping::PingResponse,
+    Milliseconds, Seconds,
+};
+
+/// What is known about one period a target was down
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Outage {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub duration: Seconds,
+    /// "Timeout" or the message of the ping error
+    pub first_error: String,
+    pub last_error: String,
+    pub timeouts: u64,
+    /// Number of times each distinct ping error message was seen
+    pub errors: BTreeMap<String, u64>,
+    /// RTT of the last successful ping before the outage started
+    pub last_good_rtt: Option<Milliseconds>,
+}
+
+impl Display for Outage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Down from {} to {}. First error: {}. Last error: {}. Timeouts: {}",
+            self.start, self.end, self.first_error, self.last_error, self.timeouts
+        )?;
+        if !self.errors.is_empty() {
+            let errors: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(msg, count)| format!("{msg:?} x{count}"))
+                .collect();
+            write!(f, ". Errors: {}", errors.join(", "))?;
+        }
+        if let Some(rtt) = self.last_good_rtt {
+            write!(f, ". Last good RTT: {rtt}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the details of an ongoing outage
+#[derive(Debug)]
+pub(crate) struct OutageTracker {
+    start: Timestamp,
+    started: Instant,
+    first_error: Option<String>,
+    last_error: Option<String>,
+    timeouts: u64,
+    errors: BTreeMap<String, u64>,
+    last_good_rtt: Option<Milliseconds>,
+}
+
+impl OutageTracker {
+    pub(crate) fn new(
+        start: Timestamp,
+        started: Instant,
+        last_good_rtt: Option<Milliseconds>,
+    ) -> Self {
+        Self {
+            start,
+            started,
+            first_error: None,
+            last_error: None,
+            timeouts: 0,
+            errors: Default::default(),
+            last_good_rtt,
+        }
+    }
+
+    pub(crate) fn record(&mut self, response: &PingResponse) {
+        let error = match response {
+            PingResponse::Timeout => {
+                self.timeouts += 1;
+                "Timeout".to_string()
+            }
+            PingResponse::ErrorPing { msg } => {
+                *self.errors.entry(msg.clone()).or_default() += 1;
+                msg.clone()
+            }
+            PingResponse::Time(_)
+            | PingResponse::ErrorOS { .. }
+            | PingResponse::ErrorProgramming { .. } => return,
+        };
+        self.first_error.get_or_insert_with(|| error.clone());
+        self.last_error = Some(error);
+    }
+
+    /// The outage ending at `end`
+    pub(crate) fn finish(self, end: Timestamp, now: Instant) -> Outage {
+        Outage {
+            start: self.start,
+            end,
+            duration: now.saturating_duration_since(self.started).as_secs().into(),
+            first_error: self.first_error.unwrap_or_default(),
+            last_error: self.last_error.unwrap_or_default(),
+            timeouts: self.timeouts,
+            errors: self.errors,
+            last_good_rtt: self.last_good_rtt,
+        }
+    }
+}
+
+/// An [`Outage`] as stored in the history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutageRecord {
+    pub target: String,
+    #[serde(flatten)]
+    pub outage: Outage,
+}
+
+/// File all outages are appended to
+const HISTORY_FILE: &str = "outages.jsonl";
+
+/// Appends the outage of `target` to the history in `folder`
+pub(crate) fn record(folder: &Path, target: &str, outage: &Outage) -> anyhow::Result<()> {
+    create_dir_all(folder).with_context(|| format!("failed to create {folder:?}"))?;
+    let path = folder.join(HISTORY_FILE);
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {path:?}"))?;
+    let record = OutageRecord {
+        target: target.to_string(),
+        outage: outage.clone(),
+    };
+    let line = serde_json::to_string(&record).context("failed to serialize outage")?;
+    writeln!(file, "{line}").with_context(|| format!("failed to write to {path:?}"))?;
+    Ok(())
+}
+
+/// Outages in the history in `folder` of `target` (or all targets) that ended on or after `since`
+pub(crate) fn query(
+    folder: &Path,
+    target: Option<&str>,
+    since: Option<NaiveDate>,
+) -> anyhow::Result<Vec<OutageRecord>> {
+    let path = folder.join(HISTORY_FILE);
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+    let mut result = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read line {} of {path:?}", i + 1))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: OutageRecord = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse line {} of {path:?}", i + 1))?;
+        if target.is_some_and(|x| x != record.target) {
+            continue;
+        }
+        let ended = record.outage.end.as_date_time().map(|x| x.date_naive());
+        if since.is_some_and(|since| ended.is_some_and(|ended| ended < since)) {
+            continue;
+        }
+        result.push(record);
+    }
+    Ok(result)
+}
+
+/// Prints the outages matching the filters, as JSON lines if `json` is set
+pub(crate) fn run_query(
+    target: Option<&str>,
+    since: Option<NaiveDate>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let records = query(Path::new(TargetHandler::BASE_FOLDER), target, since)?;
+    for record in records.iter() {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(record).context("failed to serialize outage")?
+            );
+        } else {
+            println!(
+                "{} - {} - {}",
+                record.target, record.outage.duration, record.outage
+            );
+        }
+    }
+    if !json {
+        println!("{} outages", records.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(text: &str) -> Timestamp {
+        serde_json::from_str(&format!("{text:?}")).unwrap()
+    }
+
+    fn outage(end: &str) -> Outage {
+        Outage {
+            start: timestamp("2024-05-01 09:58:00"),
+            end: timestamp(end),
+            duration: 120.into(),
+            first_error: "Timeout".to_string(),
+            last_error: "Network is unreachable".to_string(),
+            timeouts: 20,
+            errors: BTreeMap::from([("Network is unreachable".to_string(), 3)]),
+            last_good_rtt: Some(12.into()),
+        }
+    }
+
+    #[test]
+    fn history_is_filtered_by_target_and_date() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path();
+        record(folder, "Uplink", &outage("2024-05-01 10:00:00")).unwrap();
+        record(folder, "Uplink", &outage("2024-05-03 10:00:00")).unwrap();
+        record(folder, "DNS", &outage("2024-05-03 10:00:00")).unwrap();
+
+        // Act
+        let actual = query(folder, Some("Uplink"), NaiveDate::from_ymd_opt(2024, 5, 2));
+
+        // Assert
+        let actual = actual.unwrap();
+        assert_eq!(actual.len(), 1, "{actual:?}");
+        assert_eq!(actual[0].outage, outage("2024-05-03 10:00:00"));
+        assert_eq!(
+            actual[0].outage.to_string(),
+            "Down from 2024-05-01 09:58:00 to 2024-05-03 10:00:00. First error: Timeout. \
+             Last error: Network is unreachable. Timeouts: 20. \
+             Errors: \"Network is unreachable\" x3. Last good RTT: 12 ms"
+        );
+    }
+}
@@ -89,6 +89,10 @@ pub struct Target {
     /// If true this host will not attempt to be pinged
     #[serde(default)]
     pub disabled: bool,
+
+    /// Labels used to select this target in notification routing rules
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 impl From<&str> for Target {
@@ -104,6 +108,7 @@ impl From<String> for Target {
             display_name: None,
             timeout: None,
             disabled: false,
+            tags: vec![],
         }
     }
 }
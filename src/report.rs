@@ -0,0 +1,375 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event_recorder::{read_event_log, TargetHandler},
+    ping::PingResponse,
+    schedule::ScheduleConfig,
+    state_management::VERSION,
+    Milliseconds, Seconds,
+};
+
+/// Settings for the summary report of how each target did
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReportConfig {
+    /// How much time each report covers
+    #[serde(default)]
+    pub period: ReportPeriod,
+
+    /// When reports are sent (eg. "Mon 07:00" for weekly reports)
+    pub schedule: ScheduleConfig,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    #[default]
+    Daily,
+    Weekly,
+}
+
+impl ReportPeriod {
+    fn duration(&self) -> chrono::Duration {
+        match self {
+            ReportPeriod::Daily => chrono::Duration::days(1),
+            ReportPeriod::Weekly => chrono::Duration::weeks(1),
+        }
+    }
+}
+
+impl Display for ReportPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            ReportPeriod::Daily => "day",
+            ReportPeriod::Weekly => "week",
+        };
+        write!(f, "{result}")
+    }
+}
+
+/// Summary of how each target did over a [`ReportPeriod`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Report {
+    pub period: ReportPeriod,
+    /// Uptime of the monitor itself
+    pub uptime: Seconds,
+    pub targets: Vec<TargetSummary>,
+}
+
+impl Report {
+    /// Builds the report for the period ending at `end` from the event log files in `folder`.
+    /// Responses still pending (see `min_time_between_write`) need to be written first
+    pub(crate) fn from_event_files(
+        folder: &Path,
+        period: ReportPeriod,
+        uptime: Seconds,
+        end: DateTime<Local>,
+    ) -> anyhow::Result<Self> {
+        let start = end - period.duration();
+        let mut responses: BTreeMap<String, Vec<(DateTime<Local>, PingResponse)>> = BTreeMap::new();
+        for entry in fs::read_dir(folder).with_context(|| format!("failed to read {folder:?}"))? {
+            let path = entry
+                .with_context(|| format!("failed to read entry in {folder:?}"))?
+                .path();
+            let Some((date, target)) = TargetHandler::parse_file_name(&path) else {
+                continue;
+            };
+            if date < start.date_naive() || date > end.date_naive() {
+                continue;
+            }
+            let file = File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+            responses.entry(target.to_string()).or_default().extend(
+                read_event_log(BufReader::new(file), &path)?
+                    .into_iter()
+                    .filter(|(time, _)| start <= *time && *time < end)
+                    .map(|(time, timestamped)| (time, timestamped.response)),
+            );
+        }
+        let targets = responses
+            .into_iter()
+            .map(|(name, mut responses)| {
+                responses.sort_by_key(|(time, _)| *time);
+                TargetSummary::new(name, &responses, end)
+            })
+            .collect();
+        Ok(Self {
+            period,
+            uptime,
+            targets,
+        })
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Summary for the last {}. Uptime: {}. Version {VERSION}",
+            self.period, self.uptime
+        )?;
+        if self.targets.is_empty() {
+            write!(f, "\nNo responses recorded")?;
+        }
+        for target in self.targets.iter() {
+            write!(f, "\n{target}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics for one target over the period of a [`Report`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TargetSummary {
+    pub name: String,
+    /// Time from the first response in the period until the end of the period
+    pub monitored: Seconds,
+    /// Number of pings that got a reply or were lost (system errors are not counted)
+    pub pings: u64,
+    pub lost: u64,
+    pub outages: u64,
+    pub total_outage: Seconds,
+    pub longest_outage: Seconds,
+    pub rtt: Option<RttStats>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RttStats {
+    pub min: Milliseconds,
+    pub avg: Milliseconds,
+    pub p95: Milliseconds,
+    pub max: Milliseconds,
+}
+
+impl TargetSummary {
+    /// Summarizes `responses` (ordered by time) up to `end`. An outage runs from the first lost
+    /// ping until the next reply (or `end` if still ongoing)
+    pub(crate) fn new(
+        name: String,
+        responses: &[(DateTime<Local>, PingResponse)],
+        end: DateTime<Local>,
+    ) -> Self {
+        let mut result = Self {
+            name,
+            monitored: responses
+                .first()
+                .map_or(0, |(first, _)| secs_between(*first, end))
+                .into(),
+            pings: 0,
+            lost: 0,
+            outages: 0,
+            total_outage: 0.into(),
+            longest_outage: 0.into(),
+            rtt: None,
+        };
+        let mut rtts = vec![];
+        let mut outage_start = None;
+        for (time, response) in responses {
+            match response {
+                PingResponse::Time(ms) => {
+                    result.pings += 1;
+                    rtts.push(ms.as_u64());
+                    if let Some(start) = outage_start.take() {
+                        result.add_outage(secs_between(start, *time));
+                    }
+                }
+                PingResponse::Timeout | PingResponse::ErrorPing { .. } => {
+                    result.pings += 1;
+                    result.lost += 1;
+                    outage_start.get_or_insert(*time);
+                }
+                PingResponse::ErrorOS { .. } | PingResponse::ErrorProgramming { .. } => {}
+            }
+        }
+        if let Some(start) = outage_start {
+            result.add_outage(secs_between(start, end));
+        }
+        result.rtt = RttStats::new(rtts);
+        result
+    }
+
+    fn add_outage(&mut self, secs: u64) {
+        self.outages += 1;
+        self.total_outage = (self.total_outage.as_u64() + secs).into();
+        self.longest_outage = self.longest_outage.max(secs.into());
+    }
+
+    /// Percentage of the monitored time the target was not in an outage
+    pub fn uptime_percent(&self) -> f64 {
+        if self.monitored.as_u64() == 0 {
+            return 100.0;
+        }
+        let monitored = self.monitored.as_u64() as f64;
+        (monitored - self.total_outage.as_u64() as f64) / monitored * 100.0
+    }
+
+    pub fn loss_percent(&self) -> f64 {
+        if self.pings == 0 {
+            return 0.0;
+        }
+        self.lost as f64 / self.pings as f64 * 100.0
+    }
+}
+
+impl Display for TargetSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: uptime {:.2}%, {} outages totalling {} (longest {}), ",
+            self.name,
+            self.uptime_percent(),
+            self.outages,
+            self.total_outage,
+            self.longest_outage
+        )?;
+        match &self.rtt {
+            Some(RttStats { min, avg, p95, max }) => write!(
+                f,
+                "RTT min/avg/p95/max {}/{}/{}/{}, ",
+                min.as_u64(),
+                avg.as_u64(),
+                p95.as_u64(),
+                max
+            )?,
+            None => write!(f, "no RTT, ")?,
+        }
+        write!(f, "loss {:.2}%", self.loss_percent())
+    }
+}
+
+impl RttStats {
+    /// `None` if there are no values
+    fn new(mut values: Vec<u64>) -> Option<Self> {
+        values.sort_unstable();
+        let count = values.len() as u64;
+        let sum: u64 = values.iter().sum();
+        // Nearest rank method
+        let p95_index = (count * 95).div_ceil(100).max(1) - 1;
+        Some(Self {
+            min: (*values.first()?).into(),
+            avg: ((sum + count / 2) / count).into(),
+            p95: values[p95_index as usize].into(),
+            max: (*values.last()?).into(),
+        })
+    }
+}
+
+fn secs_between(start: DateTime<Local>, end: DateTime<Local>) -> u64 {
+    (end - start).num_seconds().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn at(hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 5, 1, hour, min, sec)
+            .single()
+            .unwrap()
+    }
+
+    #[test]
+    fn summarizes_outages_rtt_and_loss() {
+        // Arrange
+        let ms = |x: u64| PingResponse::Time(x.into());
+        let responses = vec![
+            (at(10, 0, 0), ms(10)),
+            (at(10, 0, 10), PingResponse::Timeout),
+            (at(10, 0, 20), PingResponse::ErrorPing { msg: "x".into() }),
+            (at(10, 0, 30), ms(30)),
+            (at(10, 0, 40), PingResponse::ErrorOS { msg: "x".into() }),
+            (at(10, 0, 50), ms(20)),
+            (at(10, 1, 0), PingResponse::Timeout),
+        ];
+
+        // Act
+        let actual = TargetSummary::new("Uplink".to_string(), &responses, at(10, 1, 40));
+
+        // Assert
+        assert_eq!(
+            actual,
+            TargetSummary {
+                name: "Uplink".to_string(),
+                monitored: 100.into(),
+                pings: 6,
+                lost: 3,
+                outages: 2,
+                total_outage: 60.into(),
+                longest_outage: 40.into(),
+                rtt: Some(RttStats {
+                    min: 10.into(),
+                    avg: 20.into(),
+                    p95: 30.into(),
+                    max: 30.into(),
+                }),
+            }
+        );
+        assert_eq!(
+            actual.to_string(),
+            "Uplink: uptime 40.00%, 2 outages totalling 0 days 00:01:00 (longest 0 days 00:00:40), \
+             RTT min/avg/p95/max 10/20/30/30 ms, loss 50.00%"
+        );
+    }
+
+    #[rstest]
+    #[case(vec![7], 7)]
+    #[case((1..=20).collect(), 19)]
+    #[case((1..=100).rev().collect(), 95)]
+    fn rtt_p95(#[case] values: Vec<u64>, #[case] expected: u64) {
+        let actual = RttStats::new(values).unwrap();
+
+        assert_eq!(actual.p95, expected.into());
+    }
+
+    #[test]
+    fn reads_only_the_period_from_event_files() {
+        // Arrange
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path();
+        fs::write(
+            folder.join("2024-04-30 Uplink events.log"),
+            concat!(
+                r#"{"timestamp":"2024-04-30 09:00:00","response":"Timeout"}"#,
+                "\n",
+                r#"{"timestamp":"2024-04-30 11:00:00","response":{"Time":12}}"#,
+            ),
+        )
+        .unwrap();
+        fs::write(
+            folder.join("2024-05-01 Uplink events.log"),
+            r#"{"timestamp":"2024-05-01 09:00:00","response":{"Time":14}}"#,
+        )
+        .unwrap();
+        fs::write(
+            folder.join("2024-04-01 Old events.log"),
+            r#"{"timestamp":"2024-04-01 09:00:00","response":"Timeout"}"#,
+        )
+        .unwrap();
+
+        // Act
+        let actual = Report::from_event_files(folder, ReportPeriod::Daily, 60.into(), at(10, 0, 0));
+
+        // Assert
+        let actual = actual.unwrap();
+        assert_eq!(actual.targets.len(), 1, "{actual:?}");
+        assert_eq!(actual.targets[0].name, "Uplink");
+        assert_eq!(actual.targets[0].pings, 2);
+        assert_eq!(actual.targets[0].lost, 0);
+        assert!(actual
+            .to_string()
+            .starts_with("Summary for the last day. Uptime: 0 days 00:01:00."));
+    }
+}
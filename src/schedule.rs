@@ -0,0 +1,400 @@
+use std::{collections::BTreeSet, fmt::Display, str::FromStr, thread, time::Duration};
+
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// When something recurring (eg. keep alive messages) should happen
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// IANA name of the timezone the schedules are in (eg. "Europe/London")
+    pub timezone: Tz,
+
+    /// Fires at every time matched by any of these (eg. "07:00", "Mon-Fri 07:00,19:00", "Sat every 6h")
+    pub at: Vec<Schedule>,
+
+    /// What to do if firings were missed (eg. the computer was suspended)
+    #[serde(default)]
+    pub missed: MissedFiring,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedFiring {
+    /// Fire once as soon as possible no matter how many were missed
+    #[default]
+    FireOnce,
+    /// Only fire at the next scheduled time
+    Skip,
+}
+
+impl ScheduleConfig {
+    /// The first firing strictly after `after`
+    pub(crate) fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let after = after.with_timezone(&self.timezone);
+        self.at
+            .iter()
+            .filter_map(|schedule| schedule.next_after(&after))
+            .min()
+            .map(|x| x.with_timezone(&Utc))
+    }
+
+    /// Calls `f` on a new thread named `name` each time the schedule fires
+    pub(crate) fn spawn<F>(self, name: &str, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        if self.at.is_empty() {
+            bail!("no times set for {name} schedule");
+        }
+        let thread_name = name.to_string();
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let mut scheduler = Scheduler::new(self, Utc::now());
+                loop {
+                    thread::sleep(scheduler.time_until_next(Utc::now()));
+                    if scheduler.poll(Utc::now()) {
+                        debug!("{thread_name} schedule fired");
+                        f();
+                    }
+                }
+            })
+            .with_context(|| format!("failed to start {name} thread"))?;
+        Ok(())
+    }
+}
+
+impl Display for ScheduleConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at: Vec<&str> = self.at.iter().map(|x| x.text.as_str()).collect();
+        write!(f, "{:?} ({})", at, self.timezone)
+    }
+}
+
+/// Tracks which firings of a [`ScheduleConfig`] have been handled
+#[derive(Debug)]
+struct Scheduler {
+    config: ScheduleConfig,
+    last_poll: DateTime<Utc>,
+}
+
+impl Scheduler {
+    /// Firings later than this are considered missed
+    const GRACE: Duration = Duration::from_secs(120);
+
+    /// Sleeping is done in steps of at most this long because the monotonic clock used by
+    /// [`thread::sleep`] does not advance while suspended
+    const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+    fn new(config: ScheduleConfig, now: DateTime<Utc>) -> Self {
+        Self {
+            config,
+            last_poll: now,
+        }
+    }
+
+    fn time_until_next(&self, now: DateTime<Utc>) -> Duration {
+        self.config
+            .next_after(self.last_poll)
+            .and_then(|next| (next - now).to_std().ok())
+            .unwrap_or_default()
+            .min(Self::MAX_SLEEP)
+    }
+
+    /// Returns true if it should fire now based on the firings since the last poll
+    fn poll(&mut self, now: DateTime<Utc>) -> bool {
+        let mut due = vec![];
+        let mut cursor = self.last_poll;
+        while let Some(next) = self.config.next_after(cursor).filter(|x| *x <= now) {
+            due.push(next);
+            cursor = next;
+        }
+        self.last_poll = now;
+        let Some(latest) = due.last() else {
+            return false;
+        };
+        let on_time = (now - *latest).to_std().unwrap_or_default() <= Self::GRACE;
+        let missed = if on_time { due.len() - 1 } else { due.len() };
+        if missed == 0 {
+            return true;
+        }
+        match self.config.missed {
+            MissedFiring::FireOnce => {
+                warn!(
+                    "{missed} scheduled firing(s) missed since {}. Firing once now",
+                    due[0]
+                );
+                true
+            }
+            MissedFiring::Skip => {
+                warn!(
+                    "{missed} scheduled firing(s) missed since {}. Skipped",
+                    due[0]
+                );
+                on_time
+            }
+        }
+    }
+}
+
+/// One line of a schedule: optional weekdays followed by either times of day or an interval
+///
+/// Intervals restart at midnight (eg. "every 7h" is 00:00, 07:00, 14:00 and 21:00). Times that
+/// do not exist because of a DST change fire an hour later and times that happen twice fire once
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    text: String,
+    weekdays: BTreeSet<u32>,
+    times: Times,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Times {
+    At(Vec<NaiveTime>),
+    Every(chrono::Duration),
+}
+
+impl Schedule {
+    /// Fires every day at `time`
+    pub(crate) fn daily(time: NaiveTime) -> Self {
+        Self {
+            text: time.to_string(),
+            weekdays: Self::all_weekdays(),
+            times: Times::At(vec![time]),
+        }
+    }
+
+    fn all_weekdays() -> BTreeSet<u32> {
+        (0..7).collect()
+    }
+
+    /// The first firing strictly after `after` in the timezone of `after`
+    pub(crate) fn next_after<T: TimeZone>(&self, after: &DateTime<T>) -> Option<DateTime<T>> {
+        let timezone = after.timezone();
+        let start_date = after.date_naive();
+        // A week and a day covers every weekday including the rest of today
+        for date in start_date.iter_days().take(8) {
+            if !self
+                .weekdays
+                .contains(&date.weekday().num_days_from_monday())
+            {
+                continue;
+            }
+            let times: Vec<NaiveTime> = match &self.times {
+                Times::At(times) => times.clone(),
+                Times::Every(interval) => {
+                    let mut result = vec![];
+                    let mut offset = chrono::Duration::zero();
+                    while offset < chrono::Duration::days(1) {
+                        result.push(NaiveTime::MIN + offset);
+                        offset += *interval;
+                    }
+                    result
+                }
+            };
+            for time in times {
+                let local = date.and_time(time);
+                let Some(candidate) =
+                    timezone.from_local_datetime(&local).earliest().or_else(|| {
+                        timezone
+                            .from_local_datetime(&(local + chrono::Duration::hours(1)))
+                            .earliest()
+                    })
+                else {
+                    continue;
+                };
+                if candidate > *after {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+
+    fn parse_weekdays(text: &str) -> anyhow::Result<BTreeSet<u32>> {
+        let mut result = BTreeSet::new();
+        for part in text.split(',') {
+            let parse = |x: &str| {
+                Weekday::from_str(x)
+                    .map(|day| day.num_days_from_monday())
+                    .map_err(|_| anyhow!("invalid weekday {x:?}"))
+            };
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (mut day, last) = (parse(first)?, parse(last)?);
+                    result.insert(day);
+                    while day != last {
+                        day = (day + 1) % 7;
+                        result.insert(day);
+                    }
+                }
+                None => {
+                    result.insert(parse(part)?);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn parse_interval(text: &str) -> anyhow::Result<chrono::Duration> {
+        let invalid = || anyhow!("invalid interval {text:?}. Expected a number followed by m or h");
+        let unit = text.chars().last().ok_or_else(invalid)?;
+        let count: i64 = text[..text.len() - unit.len_utf8()]
+            .parse()
+            .map_err(|_| invalid())?;
+        let result = match unit {
+            'm' => chrono::Duration::minutes(count),
+            'h' => chrono::Duration::hours(count),
+            _ => return Err(invalid()),
+        };
+        if result < chrono::Duration::minutes(1) || result > chrono::Duration::days(1) {
+            bail!("interval {text:?} must be between 1 minute and 24 hours");
+        }
+        Ok(result)
+    }
+
+    fn parse_times(text: &str) -> anyhow::Result<Vec<NaiveTime>> {
+        let mut result = text
+            .split(',')
+            .map(|x| {
+                NaiveTime::parse_from_str(x, "%H:%M:%S")
+                    .or_else(|_| NaiveTime::parse_from_str(x, "%H:%M"))
+                    .map_err(|_| anyhow!("invalid time {x:?}. Expected HH:MM or HH:MM:SS"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        result.sort();
+        Ok(result)
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let (weekdays, rest) = match parts.as_slice() {
+            [first, rest @ ..]
+                if first.starts_with(|c: char| c.is_ascii_alphabetic()) && *first != "every" =>
+            {
+                (Self::parse_weekdays(first)?, rest)
+            }
+            _ => (Self::all_weekdays(), parts.as_slice()),
+        };
+        let times = match rest {
+            ["every", interval] => Times::Every(Self::parse_interval(interval)?),
+            [times] => Times::At(Self::parse_times(times)?),
+            _ => bail!(
+                "invalid schedule {text:?}. Expected [WEEKDAYS] TIMES or [WEEKDAYS] every INTERVAL"
+            ),
+        };
+        Ok(Self {
+            text,
+            weekdays,
+            times,
+        })
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(value: Schedule) -> Self {
+        value.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use chrono_tz::Europe::London;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn london(text: &str) -> DateTime<Tz> {
+        NaiveDateTime::parse_from_str(text, "%F %T")
+            .unwrap()
+            .and_local_timezone(London)
+            .earliest()
+            .unwrap()
+    }
+
+    fn schedule(text: &str) -> Schedule {
+        Schedule::try_from(text.to_string()).unwrap()
+    }
+
+    #[rstest]
+    #[case("07:00", "2024-05-01 06:59:59", "2024-05-01 07:00:00")]
+    #[case("07:00", "2024-05-01 07:00:00", "2024-05-02 07:00:00")]
+    #[case("19:30,07:00", "2024-05-01 08:00:00", "2024-05-01 19:30:00")]
+    #[case("Mon-Fri 07:00", "2024-05-03 08:00:00", "2024-05-06 07:00:00")] // Fri to Mon
+    #[case("Sat,Sun 10:00:30", "2024-05-01 08:00:00", "2024-05-04 10:00:30")]
+    #[case("Fri-Mon 07:00", "2024-05-06 08:00:00", "2024-05-10 07:00:00")] // Wraps around
+    #[case("every 6h", "2024-05-01 13:00:00", "2024-05-01 18:00:00")]
+    #[case("every 7h", "2024-05-01 22:00:00", "2024-05-02 00:00:00")] // Restarts at midnight
+    #[case("Wed every 30m", "2024-05-01 23:45:00", "2024-05-08 00:00:00")]
+    #[case("01:30", "2024-03-30 12:00:00", "2024-03-31 02:30:00")] // Skipped by DST
+    fn next_firing(#[case] text: &str, #[case] after: &str, #[case] expected: &str) {
+        let actual = schedule(text).next_after(&london(after)).unwrap();
+
+        assert_eq!(actual, london(expected));
+    }
+
+    #[test]
+    fn fires_once_for_repeated_hour() {
+        // Arrange
+        let schedule = schedule("01:30");
+        // 01:30 happens twice on 2024-10-27 in London, first at 00:30 UTC
+        let first = Utc.with_ymd_and_hms(2024, 10, 27, 0, 30, 0).unwrap();
+
+        // Act
+        let actual = schedule.next_after(&first.with_timezone(&London)).unwrap();
+
+        // Assert
+        assert_eq!(actual, london("2024-10-28 01:30:00"));
+    }
+
+    #[rstest]
+    #[case("every 6")]
+    #[case("every 30s")]
+    #[case("every 25h")]
+    #[case("Mon-Fry 07:00")]
+    #[case("25:00")]
+    #[case("Mon")]
+    #[case("Mon 07:00 08:00")]
+    fn invalid_schedule(#[case] text: &str) {
+        let actual = Schedule::try_from(text.to_string());
+
+        assert!(actual.is_err(), "{actual:?}");
+    }
+
+    #[rstest]
+    #[case::on_time(MissedFiring::Skip, "2024-05-01 07:01:00", true)]
+    #[case::late_skip(MissedFiring::Skip, "2024-05-01 09:00:00", false)]
+    #[case::late_fire_once(MissedFiring::FireOnce, "2024-05-01 09:00:00", true)]
+    #[case::several_missed_skip(MissedFiring::Skip, "2024-05-03 07:00:00", true)]
+    #[case::not_due(MissedFiring::Skip, "2024-05-01 06:59:00", false)]
+    fn missed_firings(#[case] missed: MissedFiring, #[case] now: &str, #[case] expected: bool) {
+        // Arrange
+        let config = ScheduleConfig {
+            timezone: London,
+            at: vec![schedule("07:00")],
+            missed,
+        };
+        let mut scheduler =
+            Scheduler::new(config, london("2024-05-01 06:00:00").with_timezone(&Utc));
+
+        // Act
+        let actual = scheduler.poll(london(now).with_timezone(&Utc));
+
+        // Assert
+        assert_eq!(actual, expected);
+        assert!(
+            !scheduler.poll(london(now).with_timezone(&Utc)),
+            "fired twice"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::{fmt::Debug, fs, path::PathBuf, sync::RwLock};
+
+use anyhow::anyhow;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+/// Replaces secret values in text that is logged or sent out
+const REDACTED: &str = "[REDACTED]";
+
+/// Values shorter than this are likely to also appear in unrelated text that then gets redacted
+const MIN_RECOMMENDED_LEN: usize = 6;
+
+/// Every secret value loaded, longest first so a secret containing another is fully redacted
+static KNOWN_SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Where the value of a secret comes from
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum SecretSource {
+    /// Read from an environment variable
+    Env { env: String },
+    /// Read from a file (eg. docker or systemd credentials), surrounding whitespace is removed
+    File { file: PathBuf },
+    /// Set directly in the config file
+    Value(String),
+}
+
+/// A credential from the config. Its value is never included in debug output and is
+/// redacted from the logs
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(try_from = "SecretSource", into = "SecretSource")]
+pub struct Secret {
+    source: SecretSource,
+    value: String,
+}
+
+impl Secret {
+    /// The actual value, only to be used where it is needed (eg. in a request)
+    pub(crate) fn expose(&self) -> &str {
+        &self.value
+    }
+}
+
+impl TryFrom<SecretSource> for Secret {
+    type Error = anyhow::Error;
+
+    fn try_from(source: SecretSource) -> Result<Self, Self::Error> {
+        let value = match &source {
+            SecretSource::Env { env } => std::env::var(env).map_err(|e| {
+                anyhow!("failed to read secret from environment variable {env:?}: {e}")
+            })?,
+            SecretSource::File { file } => fs::read_to_string(file)
+                .map_err(|e| anyhow!("failed to read secret from {file:?}: {e}"))?
+                .trim()
+                .to_string(),
+            SecretSource::Value(value) => value.clone(),
+        };
+        register(&value);
+        Ok(Self { source, value })
+    }
+}
+
+impl From<Secret> for SecretSource {
+    fn from(value: Secret) -> Self {
+        value.source
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self::try_from(SecretSource::Value(value.to_string())).expect("direct values always load")
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            SecretSource::Env { env } => write!(f, "Secret(env: {env:?})"),
+            SecretSource::File { file } => write!(f, "Secret(file: {file:?})"),
+            SecretSource::Value(_) => write!(f, "Secret({REDACTED})"),
+        }
+    }
+}
+
+fn register(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    if value.chars().count() < MIN_RECOMMENDED_LEN {
+        warn!(
+            "a secret is shorter than {MIN_RECOMMENDED_LEN} characters. Unrelated text that \
+            contains it will also be redacted from the logs"
+        );
+    }
+    let Ok(mut known) = KNOWN_SECRETS.write() else {
+        error!("secrets lock poisoned. Unable to register secret for redaction");
+        return;
+    };
+    if !known.iter().any(|x| x == value) {
+        known.push(value.to_string());
+        known.sort_by_key(|x| std::cmp::Reverse(x.len()));
+    }
+}
+
+/// Held by tests that set or read environment variables as changing them is not thread safe
+#[cfg(test)]
+pub(crate) static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Replaces every known secret value in `text`
+pub(crate) fn redact(text: &str) -> String {
+    let mut result = text.to_string();
+    // If the lock is poisoned nothing can be registered anymore so the list is still complete
+    let known = KNOWN_SECRETS.read().unwrap_or_else(|e| e.into_inner());
+    for secret in known.iter() {
+        if result.contains(secret.as_str()) {
+            result = result.replace(secret.as_str(), REDACTED);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn loads_from_env_and_file() {
+        // Arrange
+        let _env = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let var = "CONN_MON_TEST_SECRET_ENV";
+        std::env::set_var(var, "s3cret-from-env");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        fs::write(&path, "s3cret-from-file\n").unwrap();
+        let json = format!(r#"[{{"env": "{var}"}}, {{"file": {path:?}}}, "s3cret-inline"]"#);
+
+        // Act
+        let actual: Vec<Secret> = serde_json::from_str(&json).unwrap();
+
+        // Assert
+        let values: Vec<&str> = actual.iter().map(|x| x.expose()).collect();
+        assert_eq!(
+            values,
+            ["s3cret-from-env", "s3cret-from-file", "s3cret-inline"]
+        );
+        let debug = format!("{actual:?}");
+        assert!(!debug.contains("s3cret"), "{debug}");
+        assert_eq!(
+            redact("token=s3cret-from-file url=https://x/s3cret-inline"),
+            format!("token={REDACTED} url=https://x/{REDACTED}")
+        );
+    }
+
+    #[test]
+    fn short_values_are_still_redacted() {
+        let actual = Secret::from("q7z");
+
+        assert_eq!(actual.expose(), "q7z");
+        assert_eq!(redact("pass=q7z"), format!("pass={REDACTED}"));
+    }
+
+    #[rstest]
+    #[case(
+        r#"{"env": "CONN_MON_TEST_SECRET_NOT_SET"}"#,
+        "CONN_MON_TEST_SECRET_NOT_SET"
+    )]
+    #[case(r#"{"file": "/nonexistent/secret"}"#, "/nonexistent/secret")]
+    #[case(r#"{"env": "X", "file": "/x"}"#, "did not match any variant")]
+    fn missing_secret_is_reported(#[case] json: &str, #[case] expected: &str) {
+        let _env = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let actual = serde_json::from_str::<Secret>(json).unwrap_err();
+
+        assert!(actual.to_string().contains(expected), "{actual}");
+    }
+}
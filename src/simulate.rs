@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context};
+use chrono::{DateTime, Local};
+
+use crate::{
+    config::Config,
+    event_recorder::{read_event_log, TargetHandler, TimestampedResponse},
+    state_management::{Event, MonitorState},
+};
+
+/// An event the monitor would have produced for a recorded response
+#[derive(Debug)]
+pub(crate) struct SimulatedEvent {
+    pub(crate) timestamp: DateTime<Local>,
+    pub(crate) target: String,
+    pub(crate) event: Event,
+}
+
+impl Display for SimulatedEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} - {}",
+            self.timestamp.format("%F %T"),
+            self.target,
+            self.event
+        )
+    }
+}
+
+/// Replays the responses in the event log files at `paths` (or all in the events folder if empty)
+/// and prints the events that `config` would have produced
+pub(crate) fn run(config: &Config, paths: &[PathBuf]) -> anyhow::Result<()> {
+    let paths = if paths.is_empty() {
+        log_files_in(Path::new(TargetHandler::BASE_FOLDER))?
+    } else {
+        paths.to_vec()
+    };
+    let mut recorded: BTreeMap<String, Vec<(DateTime<Local>, TimestampedResponse)>> =
+        BTreeMap::new();
+    for path in paths.iter() {
+        let Some((_date, target)) = TargetHandler::parse_file_name(path) else {
+            bail!("unable to get target from the name of {path:?}. Expected \"<date> <target> events.log\"");
+        };
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        recorded
+            .entry(target.to_string())
+            .or_default()
+            .extend(read_event_log(BufReader::new(file), path)?);
+    }
+    let response_count: usize = recorded.values().map(Vec::len).sum();
+    let target_count = recorded.len();
+
+    let timeline = simulate(config, recorded);
+    for event in timeline.iter() {
+        println!("{event}");
+    }
+    println!(
+        "{} events from {response_count} responses of {target_count} targets",
+        timeline.len()
+    );
+    Ok(())
+}
+
+/// Feeds the responses of each target through a [`MonitorState`] with a clock that follows the
+/// recorded timestamps and returns the resulting events in order
+pub(crate) fn simulate(
+    config: &Config,
+    recorded: BTreeMap<String, Vec<(DateTime<Local>, TimestampedResponse)>>,
+) -> Vec<SimulatedEvent> {
+    let Some(first) = recorded.values().flatten().map(|(time, _)| *time).min() else {
+        return vec![];
+    };
+    let clock_start = Instant::now();
+    let mut result = vec![];
+    for (target, mut responses) in recorded {
+        // Files may be given in any order
+        responses.sort_by_key(|(time, _)| *time);
+        let mut state = MonitorState::new(config);
+        for (time, response) in responses {
+            let now = clock_start + (time - first).to_std().unwrap_or_default();
+            if let Some(event) = state.process_response_at(&response, now) {
+                result.push(SimulatedEvent {
+                    timestamp: time,
+                    target: target.clone(),
+                    event,
+                });
+            }
+        }
+    }
+    result.sort_by_key(|x| x.timestamp);
+    result
+}
+
+fn log_files_in(folder: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut result = vec![];
+    for entry in fs::read_dir(folder).with_context(|| format!("failed to read {folder:?}"))? {
+        let path = entry
+            .with_context(|| format!("failed to read entry in {folder:?}"))?
+            .path();
+        if TargetHandler::parse_file_name(&path).is_some() {
+            result.push(path);
+        }
+    }
+    if result.is_empty() {
+        bail!("no event log files found in {folder:?}");
+    }
+    result.sort();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_management::EventKind;
+
+    use super::*;
+
+    /// Lines as written to the event log, starting at 10:00:00 with one every `step` seconds
+    fn log_lines(step: u32, responses: &[&str]) -> String {
+        responses
+            .iter()
+            .enumerate()
+            .map(|(i, response)| {
+                let secs = i as u32 * step;
+                format!(
+                    r#"{{"timestamp":"2024-05-01 10:{:02}:{:02}","response":{response}}}"#,
+                    secs / 60,
+                    secs % 60
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn replays_outage_with_recorded_timing() {
+        // Arrange
+        let config: Config = serde_json::from_str(
+            r#"{"targets": [], "min_time_before_first_down_notification": 20, "notify_remind_interval": 60}"#,
+        )
+        .unwrap();
+        let mut responses = vec![r#"{"Time":12}"#];
+        responses.extend([r#""Timeout""#; 18]);
+        responses.extend([r#"{"ErrorPing":{"msg":"Network is unreachable"}}"#; 2]);
+        responses.push(r#"{"Time":15}"#);
+        let lines = log_lines(5, &responses);
+        let recorded = read_event_log(lines.as_bytes(), Path::new("test")).unwrap();
+
+        // Act
+        let actual = simulate(&config, BTreeMap::from([("Uplink".to_string(), recorded)]));
+
+        // Assert
+        let events: Vec<(String, EventKind)> = actual
+            .iter()
+            .map(|x| (x.timestamp.format("%T").to_string(), x.event.kind()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("10:00:25".to_string(), EventKind::ConnectionFailed),
+                ("10:01:25".to_string(), EventKind::ConnectionStillDown),
+                ("10:01:45".to_string(), EventKind::ConnectionRestoredAfter),
+            ]
+        );
+        assert_eq!(
+            actual[2].to_string(),
+            "2024-05-01 10:01:45 - Uplink - Connection back UP. Outage duration WAS 0 days 00:01:40. \
+             Down from 2024-05-01 10:00:05 to 2024-05-01 10:01:45. First error: Timeout. \
+             Last error: Network is unreachable. Timeouts: 18. \
+             Errors: \"Network is unreachable\" x2. Last good RTT: 12 ms"
+        );
+    }
+}
@@ -1,16 +1,29 @@
 use std::{fmt::Display, time::Instant};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
-    config::Config, event_recorder::TimestampedResponse, ping::PingResponse, units::Seconds,
+    config::Config,
+    event_recorder::TimestampedResponse,
+    outage::{Outage, OutageTracker},
+    ping::PingResponse,
+    report::Report,
+    units::{Milliseconds, Seconds},
 };
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug)]
 pub struct MonitorState {
     state: State,
     notify_remind_interval: Seconds,
     min_time_before_first_down_notification: Seconds,
+    /// RTT of the most recent successful ping
+    last_rtt: Option<Milliseconds>,
+    /// Details of the current Down period
+    outage: Option<OutageTracker>,
+    /// Set when a Down period ends until taken
+    finished_outage: Option<Outage>,
 }
 
 #[derive(Debug)]
@@ -27,17 +40,17 @@ enum State {
     },
 }
 impl State {
-    fn down_now() -> Self {
+    fn down_at(now: Instant) -> Self {
         Self::Down {
-            start: Instant::now(),
+            start: now,
             last_notify: None,
         }
     }
 
-    fn error_now() -> Self {
+    fn error_at(now: Instant) -> Self {
         Self::SystemError {
-            start: Instant::now(),
-            last_notify: Instant::now(),
+            start: now,
+            last_notify: now,
         }
     }
 }
@@ -48,6 +61,24 @@ impl MonitorState {
             state: State::Start,
             notify_remind_interval: config.notify_remind_interval,
             min_time_before_first_down_notification: config.min_time_before_first_down_notification,
+            last_rtt: None,
+            outage: None,
+            finished_outage: None,
+        }
+    }
+
+    /// The details of the last Down period that ended, if not already taken
+    pub fn take_finished_outage(&mut self) -> Option<Outage> {
+        self.finished_outage.take()
+    }
+
+    /// The user facing status of the target based on the current state
+    pub fn status(&self) -> Status {
+        match self.state {
+            State::Start => Status::Unknown,
+            State::Up => Status::Up,
+            State::Down { .. } => Status::Down,
+            State::SystemError { .. } => Status::SystemError,
         }
     }
 
@@ -56,6 +87,48 @@ impl MonitorState {
         &mut self,
         timestamped_response: &TimestampedResponse,
     ) -> Option<Event> {
+        self.process_response_at(timestamped_response, Instant::now())
+    }
+
+    /// Same as [`Self::process_response`] but with `now` as the current time (eg. when replaying recorded responses)
+    pub fn process_response_at(
+        &mut self,
+        timestamped_response: &TimestampedResponse,
+        now: Instant,
+    ) -> Option<Event> {
+        let was_down = matches!(self.state, State::Down { .. });
+        let mut result = self.transition(timestamped_response, now);
+        let TimestampedResponse {
+            timestamp,
+            response,
+        } = timestamped_response;
+        if matches!(self.state, State::Down { .. }) {
+            self.outage
+                .get_or_insert_with(|| OutageTracker::new(timestamp.clone(), now, self.last_rtt))
+                .record(response);
+        } else if was_down {
+            if let Some(tracker) = self.outage.take() {
+                let outage = tracker.finish(timestamp.clone(), now);
+                if let Some(Event::ConnectionRestoredAfter(_, details)) = &mut result {
+                    *details = Some(Box::new(outage.clone()));
+                }
+                self.finished_outage = Some(outage);
+            }
+        }
+        if let PingResponse::Time(ms) = response {
+            self.last_rtt = Some(*ms);
+        }
+        result
+    }
+
+    /// Updates the state and returns the event caused by the transition (if any)
+    fn transition(
+        &mut self,
+        timestamped_response: &TimestampedResponse,
+        now: Instant,
+    ) -> Option<Event> {
+        let since =
+            |start: Instant| -> Seconds { now.saturating_duration_since(start).as_secs().into() };
         let ping_response = &timestamped_response.response;
         let result;
         (result, self.state) = match self.state {
@@ -66,86 +139,81 @@ impl MonitorState {
                         (
                             Some(Event::ConnectionFailed(0.into())),
                             State::Down {
-                                start: Instant::now(),
-                                last_notify: Some(Instant::now()),
+                                start: now,
+                                last_notify: Some(now),
                             },
                         )
                     } else {
-                        (None, State::down_now())
+                        (None, State::down_at(now))
                     }
                 }
                 PingResponse::ErrorOS { msg } | PingResponse::ErrorProgramming { msg } => {
-                    Self::new_system_error(msg)
+                    Self::new_system_error(msg, now)
                 }
             },
             State::Down { start, last_notify } => match ping_response {
                 PingResponse::Time(_ms) => {
                     let notification = if last_notify.is_some() {
-                        Some(Event::ConnectionRestoredAfter(
-                            start.elapsed().as_secs().into(),
-                        ))
+                        Some(Event::ConnectionRestoredAfter(since(start), None))
                     } else {
                         None
                     };
                     (notification, State::Up)
                 }
                 PingResponse::Timeout => {
-                    let notification = if self.should_notify() {
+                    let notification = if self.should_notify(now) {
                         if last_notify.is_none() {
-                            Some(Event::ConnectionFailed(start.elapsed().as_secs().into()))
+                            Some(Event::ConnectionFailed(since(start)))
                         } else {
-                            Some(Event::ConnectionStillDown(start.elapsed().as_secs().into()))
+                            Some(Event::ConnectionStillDown(since(start)))
                         }
                     } else {
                         None
                     };
                     let last_notify = if notification.is_some() {
-                        Some(Instant::now())
+                        Some(now)
                     } else {
                         last_notify
                     };
                     (notification, State::Down { start, last_notify })
                 }
                 PingResponse::ErrorPing { msg } => {
-                    let notification = if self.should_notify() {
+                    let notification = if self.should_notify(now) {
                         if last_notify.is_none() {
-                            Some(Event::ConnectionError(
-                                start.elapsed().as_secs().into(),
-                                msg.to_string(),
-                            ))
+                            Some(Event::ConnectionError(since(start), msg.to_string()))
                         } else {
-                            Some(Event::ConnectionStillDown(start.elapsed().as_secs().into()))
+                            Some(Event::ConnectionStillDown(since(start)))
                         }
                     } else {
                         None
                     };
                     let last_notify = if notification.is_some() {
-                        Some(Instant::now())
+                        Some(now)
                     } else {
                         last_notify
                     };
                     (notification, State::Down { start, last_notify })
                 }
                 PingResponse::ErrorOS { msg } | PingResponse::ErrorProgramming { msg } => {
-                    (Some(Event::SystemError(msg.clone())), State::error_now())
+                    (Some(Event::SystemError(msg.clone())), State::error_at(now))
                 }
             },
             State::SystemError { start, last_notify } => match ping_response {
                 PingResponse::Time(_ms) => (
-                    Some(Event::ConnectionRestoredAfter(
-                        start.elapsed().as_secs().into(),
-                    )),
+                    Some(Event::ConnectionRestoredAfter(since(start), None)),
                     State::Up,
                 ),
-                PingResponse::Timeout | PingResponse::ErrorPing { .. } => (None, State::down_now()),
+                PingResponse::Timeout | PingResponse::ErrorPing { .. } => {
+                    (None, State::down_at(now))
+                }
                 PingResponse::ErrorOS { .. } | PingResponse::ErrorProgramming { .. } => {
-                    let notification = if self.should_notify() {
-                        Some(Event::StillSystemError(start.elapsed().as_secs().into()))
+                    let notification = if self.should_notify(now) {
+                        Some(Event::StillSystemError(since(start)))
                     } else {
                         None
                     };
                     let last_notify = if notification.is_some() {
-                        Instant::now()
+                        now
                     } else {
                         last_notify
                     };
@@ -156,43 +224,184 @@ impl MonitorState {
         result
     }
 
-    fn new_system_error(msg: &str) -> (Option<Event>, State) {
+    fn new_system_error(msg: &str, now: Instant) -> (Option<Event>, State) {
         (
             Some(Event::SystemError(msg.to_string())),
-            State::error_now(),
+            State::error_at(now),
         )
     }
 
     /// Meant for Down and SystemError only but couldn't find easy way to make function only compile if in one of those states
     /// Others just always return true as this function is not meant for them
-    fn should_notify(&self) -> bool {
+    fn should_notify(&self, now: Instant) -> bool {
         // TODO: Support two reminder intervals (One for after target has been down for longer period of time)
         let last_notify = match self.state {
             State::Start | State::Up => return true,
             State::Down { start, last_notify } => match last_notify {
                 Some(last) => last,
                 None => {
-                    return start.elapsed().as_secs()
+                    return now.saturating_duration_since(start).as_secs()
                         >= self.min_time_before_first_down_notification.into()
                 }
             },
             State::SystemError { last_notify, .. } => last_notify,
         };
 
-        last_notify.elapsed().as_secs() >= self.notify_remind_interval.into()
+        now.saturating_duration_since(last_notify).as_secs() >= self.notify_remind_interval.into()
+    }
+}
+
+/// Simplified view of [`State`] for reporting
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Unknown,
+    Up,
+    Down,
+    SystemError,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            Status::Unknown => "Unknown",
+            Status::Up => "Up",
+            Status::Down => "Down",
+            Status::SystemError => "System Error",
+        };
+        write!(f, "{result}")
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Event {
     Startup,
     IAmAlive(Seconds),
+    /// Sent instead of [`Event::IAmAlive`] when reports are configured
+    Report(Box<Report>),
     ConnectionFailed(Seconds),
     ConnectionError(Seconds, String),
     ConnectionStillDown(Seconds),
-    ConnectionRestoredAfter(Seconds),
+    /// Holds the details of the outage if the target was down (not for system errors)
+    ConnectionRestoredAfter(Seconds, Option<Box<Outage>>),
     SystemError(String),
     StillSystemError(Seconds),
+    /// Several events sent together, holds the number of events
+    Digest(usize),
+    /// Sent on request to check that notifications are delivered
+    Test,
+}
+
+/// The kind of an [`Event`] without any of the associated data
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Startup,
+    IAmAlive,
+    Report,
+    ConnectionFailed,
+    ConnectionError,
+    ConnectionStillDown,
+    ConnectionRestoredAfter,
+    SystemError,
+    StillSystemError,
+    Digest,
+    Test,
+}
+
+/// How urgently an event needs the attention of a person
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Only informational (eg. keep alive)
+    Info,
+    /// Updates about a problem that was already reported or has been resolved
+    Low,
+    /// A new problem
+    High,
+}
+
+impl EventKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            EventKind::Startup
+            | EventKind::IAmAlive
+            | EventKind::Report
+            | EventKind::Digest
+            | EventKind::Test => Severity::Info,
+            EventKind::ConnectionStillDown
+            | EventKind::ConnectionRestoredAfter
+            | EventKind::StillSystemError => Severity::Low,
+            EventKind::ConnectionFailed | EventKind::ConnectionError | EventKind::SystemError => {
+                Severity::High
+            }
+        }
+    }
+
+    /// Name used for this kind in config files and machine readable output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Startup => "startup",
+            EventKind::IAmAlive => "i_am_alive",
+            EventKind::Report => "report",
+            EventKind::ConnectionFailed => "connection_failed",
+            EventKind::ConnectionError => "connection_error",
+            EventKind::ConnectionStillDown => "connection_still_down",
+            EventKind::ConnectionRestoredAfter => "connection_restored_after",
+            EventKind::SystemError => "system_error",
+            EventKind::StillSystemError => "still_system_error",
+            EventKind::Digest => "digest",
+            EventKind::Test => "test",
+        }
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::Startup => EventKind::Startup,
+            Event::IAmAlive(_) => EventKind::IAmAlive,
+            Event::Report(_) => EventKind::Report,
+            Event::ConnectionFailed(_) => EventKind::ConnectionFailed,
+            Event::ConnectionError(..) => EventKind::ConnectionError,
+            Event::ConnectionStillDown(_) => EventKind::ConnectionStillDown,
+            Event::ConnectionRestoredAfter(..) => EventKind::ConnectionRestoredAfter,
+            Event::SystemError(_) => EventKind::SystemError,
+            Event::StillSystemError(_) => EventKind::StillSystemError,
+            Event::Digest(_) => EventKind::Digest,
+            Event::Test => EventKind::Test,
+        }
+    }
+
+    /// The outage (or uptime for [`Event::IAmAlive`] and [`Event::Report`]) duration if the event has one
+    pub fn duration(&self) -> Option<Seconds> {
+        match self {
+            Event::Startup | Event::SystemError(_) | Event::Digest(_) | Event::Test => None,
+            Event::Report(report) => Some(report.uptime),
+            Event::IAmAlive(duration)
+            | Event::ConnectionFailed(duration)
+            | Event::ConnectionError(duration, _)
+            | Event::ConnectionStillDown(duration)
+            | Event::ConnectionRestoredAfter(duration, _)
+            | Event::StillSystemError(duration) => Some(*duration),
+        }
+    }
+
+    /// The details of the outage that ended if the event has them
+    pub fn outage(&self) -> Option<&Outage> {
+        match self {
+            Event::ConnectionRestoredAfter(_, outage) => outage.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The error message if the event has one
+    pub fn error_msg(&self) -> Option<&str> {
+        match self {
+            Event::ConnectionError(_, msg) | Event::SystemError(msg) => Some(msg),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Event {
@@ -202,6 +411,7 @@ impl Display for Event {
             Event::IAmAlive(uptime) => {
                 format!("I'm still alive. Uptime: {uptime}. Version {VERSION}")
             }
+            Event::Report(report) => report.to_string(),
             Event::ConnectionFailed(duration) => {
                 format!("NEW Down. Outage duration IS {duration}")
             }
@@ -214,12 +424,17 @@ impl Display for Event {
             Event::StillSystemError(duration) => {
                 format!("System Error persists. Error duration IS {duration}")
             }
-            Event::ConnectionRestoredAfter(duration) => {
-                format!("Connection back UP. Outage duration WAS {duration}")
-            }
+            Event::ConnectionRestoredAfter(duration, outage) => match outage {
+                Some(outage) => {
+                    format!("Connection back UP. Outage duration WAS {duration}. {outage}")
+                }
+                None => format!("Connection back UP. Outage duration WAS {duration}"),
+            },
             Event::SystemError(err_msg) => {
                 format!("System error with message {err_msg:?}")
             }
+            Event::Digest(count) => format!("{count} events:"),
+            Event::Test => format!("Test notification. Version {VERSION}"),
         };
         write!(f, "{result}")
     }
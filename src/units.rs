@@ -3,7 +3,7 @@ use std::fmt::Display;
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy)]
 pub struct Milliseconds(u64);
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy)]
@@ -22,6 +22,15 @@ impl Milliseconds {
     pub(crate) const fn new(value: u64) -> Self {
         Self(value)
     }
+    pub(crate) fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for Milliseconds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ms", self.0)
+    }
 }
 
 impl Display for Seconds {